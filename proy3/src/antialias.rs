@@ -0,0 +1,102 @@
+use rand::Rng;
+
+/// How the sub-pixel sample positions are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplePattern {
+    #[default]
+    Stratified, // One jittered sample in each cell of an N×N grid
+    Random,     // N×N uniformly distributed samples
+}
+
+/// Reconstruction filter weighting the samples of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFilter {
+    Box,      // Plain average over the pixel
+    #[default]
+    Tent,     // Linear falloff, reaches into the neighbouring pixels
+    Gaussian, // Smoothest, slightly blurry
+}
+
+impl PixelFilter {
+    /// Half-width of the filter footprint, in pixels.
+    pub fn radius(self) -> f32 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian => 1.5,
+        }
+    }
+
+    /// Weight of a sample at offset (`dx`, `dy`) from the pixel centre.
+    pub fn weight(self, dx: f32, dy: f32) -> f32 {
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => (1.0 - dx.abs()).max(0.0) * (1.0 - dy.abs()).max(0.0),
+            PixelFilter::Gaussian => {
+                let sigma = 0.5;
+                (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+            }
+        }
+    }
+}
+
+/// Supersampling settings for `render`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AntiAliasing {
+    pub samples: usize,         // Samples per axis; 1 shoots a single ray through each pixel
+    pub pattern: SamplePattern,
+    pub filter: PixelFilter,
+    pub adaptive: Option<f32>,  // Only supersample pixels whose contrast with a neighbour exceeds this
+}
+
+impl Default for AntiAliasing {
+    fn default() -> Self {
+        AntiAliasing {
+            samples: 1,
+            pattern: SamplePattern::default(),
+            filter: PixelFilter::default(),
+            adaptive: None,
+        }
+    }
+}
+
+impl AntiAliasing {
+    pub fn new(samples: usize, pattern: SamplePattern, filter: PixelFilter) -> Self {
+        AntiAliasing { samples, pattern, filter, adaptive: None }
+    }
+
+    /// Returns true if more than one ray is traced per pixel.
+    pub fn is_enabled(&self) -> bool {
+        self.samples > 1
+    }
+
+    /// Sub-pixel offsets from the pixel centre, spread over the filter footprint.
+    pub fn offsets<R: Rng>(&self, rng: &mut R) -> Vec<(f32, f32)> {
+        let n = self.samples.max(1);
+        let radius = self.filter.radius();
+        let cell = 2.0 * radius / n as f32;
+
+        let mut offsets = Vec::with_capacity(n * n);
+        for j in 0..n {
+            for i in 0..n {
+                let (u, v) = match self.pattern {
+                    SamplePattern::Stratified => (
+                        (i as f32 + rng.gen::<f32>()) * cell,
+                        (j as f32 + rng.gen::<f32>()) * cell,
+                    ),
+                    SamplePattern::Random => (
+                        rng.gen::<f32>() * 2.0 * radius,
+                        rng.gen::<f32>() * 2.0 * radius,
+                    ),
+                };
+                offsets.push((u - radius, v - radius));
+            }
+        }
+        offsets
+    }
+}
+
+/// Relative luminance difference used by the adaptive mode.
+pub fn contrast(a: f32, b: f32) -> f32 {
+    (a - b).abs() / (a + b).max(1e-4)
+}
@@ -0,0 +1,503 @@
+use nalgebra_glm::Vec3;
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+const BIN_COUNT: usize = 12;      // Number of SAH buckets per axis
+const MAX_LEAF_SIZE: usize = 4;   // Leaves larger than this are always split when possible
+const MAX_DEPTH: usize = 48;      // Keeps the traversal stack bounded
+const TRAVERSAL_COST: f32 = 1.0;  // Cost of visiting a node, relative to one object test
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Creates an inverted box that any union will replace.
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: self.min.inf(point),
+            max: self.max.sup(point),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let extent = self.max - self.min;
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0; // Empty box
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Slab test against the ray, returning the entry distance if the box is hit before `max_distance`.
+    pub fn hit(&self, ray_origin: &Vec3, inv_direction: &Vec3, max_distance: f32) -> Option<f32> {
+        let mut tmin = 0.0f32;
+        let mut tmax = max_distance;
+
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - ray_origin[axis]) * inv_direction[axis];
+            let t2 = (self.max[axis] - ray_origin[axis]) * inv_direction[axis];
+
+            // f32::min/max drop NaNs, which appear when the ray lies in a slab plane
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+
+        if tmin <= tmax {
+            Some(tmin)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if `point` lies inside the box or on its boundary.
+    pub fn contains(&self, point: &Vec3) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] <= self.max[axis])
+    }
+
+    /// Distance at which a ray starting inside the box leaves it.
+    pub fn exit_distance(&self, ray_origin: &Vec3, inv_direction: &Vec3) -> f32 {
+        (0..3)
+            .map(|axis| {
+                let t1 = (self.min[axis] - ray_origin[axis]) * inv_direction[axis];
+                let t2 = (self.max[axis] - ray_origin[axis]) * inv_direction[axis];
+                t1.max(t2)
+            })
+            .filter(|t| !t.is_nan())
+            .fold(f32::INFINITY, f32::min)
+            .max(0.0)
+    }
+}
+
+/// Trait for objects that can be stored in a `Bvh`.
+pub trait Bounded {
+    /// Returns the world-space bounding box of the object.
+    fn bounds(&self) -> Aabb;
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    bounds: Aabb,
+    first: usize, // First child for interior nodes, first entry in `indices` for leaves
+    count: usize, // Number of objects in a leaf, 0 for interior nodes
+}
+
+/// Bounding volume hierarchy over a slice of objects, built with the surface area heuristic.
+///
+/// The hierarchy only stores indices, so queries take the same slice it was built from.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds a new hierarchy over `objects`.
+    pub fn build<T: Bounded>(objects: &[T]) -> Self {
+        let boxes: Vec<Aabb> = objects.iter().map(Bounded::bounds).collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * boxes.len()),
+            indices: (0..boxes.len()).collect(),
+        };
+
+        if boxes.is_empty() {
+            return bvh;
+        }
+
+        bvh.nodes.push(BvhNode {
+            bounds: Aabb::empty(),
+            first: 0,
+            count: boxes.len(),
+        });
+        bvh.subdivide(0, &boxes, 0);
+        bvh
+    }
+
+    /// Updates the node bounds after objects moved, keeping the tree topology.
+    /// Falls back to a full rebuild if objects were added or removed.
+    pub fn refit<T: Bounded>(&mut self, objects: &[T]) {
+        if objects.len() != self.indices.len() {
+            *self = Bvh::build(objects);
+            return;
+        }
+
+        // Children are always stored after their parent, so a reverse sweep is bottom-up
+        for i in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[i];
+            let bounds = if node.count > 0 {
+                self.indices[node.first..node.first + node.count]
+                    .iter()
+                    .fold(Aabb::empty(), |acc, &index| acc.union(&objects[index].bounds()))
+            } else {
+                self.nodes[node.first].bounds.union(&self.nodes[node.first + 1].bounds)
+            };
+            self.nodes[i].bounds = bounds;
+        }
+    }
+
+    /// Finds the closest intersection along the ray, like a linear scan over `objects` would.
+    pub fn closest_hit<T: RayIntersect>(&self, objects: &[T], ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        debug_assert_eq!(objects.len(), self.indices.len());
+
+        let mut intersect = Intersect::empty();
+        let mut zbuffer = f32::INFINITY;
+
+        if self.nodes.is_empty() {
+            return intersect;
+        }
+
+        let inv_direction = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        let mut stack = [0usize; MAX_DEPTH + 1];
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+
+            if node.bounds.hit(ray_origin, &inv_direction, zbuffer).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &index in &self.indices[node.first..node.first + node.count] {
+                    let i = objects[index].ray_intersect(ray_origin, ray_direction);
+                    if i.is_intersecting && i.distance < zbuffer {
+                        zbuffer = i.distance;
+                        intersect = i;
+                    }
+                }
+                continue;
+            }
+
+            // Visit the nearer child first so the far one can be culled by `zbuffer`
+            let left = node.first;
+            let right = node.first + 1;
+            let left_hit = self.nodes[left].bounds.hit(ray_origin, &inv_direction, zbuffer);
+            let right_hit = self.nodes[right].bounds.hit(ray_origin, &inv_direction, zbuffer);
+
+            match (left_hit, right_hit) {
+                (Some(l), Some(r)) => {
+                    let (near, far) = if l <= r { (left, right) } else { (right, left) };
+                    stack[stack_len] = far;
+                    stack[stack_len + 1] = near;
+                    stack_len += 2;
+                }
+                (Some(_), None) => {
+                    stack[stack_len] = left;
+                    stack_len += 1;
+                }
+                (None, Some(_)) => {
+                    stack[stack_len] = right;
+                    stack_len += 1;
+                }
+                (None, None) => {}
+            }
+        }
+
+        intersect
+    }
+
+    /// Returns true as soon as any object is hit closer than `max_distance`.
+    pub fn any_hit<T: RayIntersect>(&self, objects: &[T], ray_origin: &Vec3, ray_direction: &Vec3, max_distance: f32) -> bool {
+        debug_assert_eq!(objects.len(), self.indices.len());
+
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inv_direction = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        let mut stack = [0usize; MAX_DEPTH + 1];
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+
+            if node.bounds.hit(ray_origin, &inv_direction, max_distance).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &index in &self.indices[node.first..node.first + node.count] {
+                    let i = objects[index].ray_intersect(ray_origin, ray_direction);
+                    if i.is_intersecting && i.distance < max_distance {
+                        return true;
+                    }
+                }
+            } else {
+                stack[stack_len] = node.first;
+                stack[stack_len + 1] = node.first + 1;
+                stack_len += 2;
+            }
+        }
+
+        false
+    }
+
+    /// Calls `visit` with every object whose bounds contain `point`, skipping subtrees that don't.
+    pub fn for_each_containing<T: Bounded>(&self, objects: &[T], point: &Vec3, mut visit: impl FnMut(&T)) {
+        debug_assert_eq!(objects.len(), self.indices.len());
+
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = [0usize; MAX_DEPTH + 1];
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+
+            if !node.bounds.contains(point) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &index in &self.indices[node.first..node.first + node.count] {
+                    if objects[index].bounds().contains(point) {
+                        visit(&objects[index]);
+                    }
+                }
+            } else {
+                stack[stack_len] = node.first;
+                stack[stack_len + 1] = node.first + 1;
+                stack_len += 2;
+            }
+        }
+    }
+
+    /// Recursively splits a node using binned SAH.
+    fn subdivide(&mut self, node_index: usize, boxes: &[Aabb], depth: usize) {
+        let first = self.nodes[node_index].first;
+        let count = self.nodes[node_index].count;
+        let range = first..first + count;
+
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &index in &self.indices[range.clone()] {
+            bounds = bounds.union(&boxes[index]);
+            centroid_bounds = centroid_bounds.grow(&boxes[index].centroid());
+        }
+        self.nodes[node_index].bounds = bounds;
+
+        if count <= 1 || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let (axis, split_bin, split_cost) = match find_split(&self.indices[range.clone()], boxes, &centroid_bounds) {
+            Some(split) => split,
+            None => return, // All centroids coincide, nothing to split on
+        };
+
+        let leaf_cost = count as f32;
+        let parent_area = bounds.surface_area().max(f32::EPSILON);
+        if count <= MAX_LEAF_SIZE && TRAVERSAL_COST + split_cost / parent_area >= leaf_cost {
+            return;
+        }
+
+        // Partition the indices in place around the chosen bin boundary
+        let mut mid = first;
+        for i in range {
+            let index = self.indices[i];
+            if bin_of(&boxes[index].centroid(), &centroid_bounds, axis) < split_bin {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        if mid == first || mid == first + count {
+            return;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first, count: mid - first });
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first: mid, count: first + count - mid });
+        self.nodes[node_index].first = left;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(left, boxes, depth + 1);
+        self.subdivide(left + 1, boxes, depth + 1);
+    }
+}
+
+fn bin_of(centroid: &Vec3, centroid_bounds: &Aabb, axis: usize) -> usize {
+    let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+    let relative = (centroid[axis] - centroid_bounds.min[axis]) / extent;
+    ((relative * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+}
+
+/// Returns the axis, bin boundary and unnormalized SAH cost of the cheapest split.
+fn find_split(indices: &[usize], boxes: &[Aabb], centroid_bounds: &Aabb) -> Option<(usize, usize, f32)> {
+    let mut best: Option<(usize, usize, f32)> = None;
+
+    for axis in 0..3 {
+        if centroid_bounds.max[axis] - centroid_bounds.min[axis] <= 0.0 {
+            continue;
+        }
+
+        let mut bin_bounds = [Aabb::empty(); BIN_COUNT];
+        let mut bin_counts = [0usize; BIN_COUNT];
+        for &index in indices {
+            let bin = bin_of(&boxes[index].centroid(), centroid_bounds, axis);
+            bin_bounds[bin] = bin_bounds[bin].union(&boxes[index]);
+            bin_counts[bin] += 1;
+        }
+
+        // Sweep from the right to get the area and count of everything above each boundary
+        let mut right_area = [0.0f32; BIN_COUNT];
+        let mut right_count = [0usize; BIN_COUNT];
+        let mut acc_bounds = Aabb::empty();
+        let mut acc_count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            acc_bounds = acc_bounds.union(&bin_bounds[bin]);
+            acc_count += bin_counts[bin];
+            right_area[bin] = acc_bounds.surface_area();
+            right_count[bin] = acc_count;
+        }
+
+        let mut acc_bounds = Aabb::empty();
+        let mut acc_count = 0;
+        for split in 1..BIN_COUNT {
+            acc_bounds = acc_bounds.union(&bin_bounds[split - 1]);
+            acc_count += bin_counts[split - 1];
+            if acc_count == 0 || right_count[split] == 0 {
+                continue;
+            }
+
+            let cost = acc_bounds.surface_area() * acc_count as f32 + right_area[split] * right_count[split] as f32;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::object::Cube;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_cubes(rng: &mut StdRng, count: usize) -> Vec<Cube> {
+        let material = Material::new(Color::new(255, 255, 255), 10.0, [1.0, 0.0], 0.0, 0.0, 1.0);
+        (0..count)
+            .map(|_| {
+                let min = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                let size = Vec3::new(rng.gen_range(0.1..2.0), rng.gen_range(0.1..2.0), rng.gen_range(0.1..2.0));
+                Cube::new(min, min + size, material.clone())
+            })
+            .collect()
+    }
+
+    fn random_ray(rng: &mut StdRng) -> (Vec3, Vec3) {
+        let origin = Vec3::new(rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0));
+        let direction = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)).normalize();
+        (origin, direction)
+    }
+
+    fn brute_force(objects: &[Cube], origin: &Vec3, direction: &Vec3) -> Intersect {
+        let mut intersect = Intersect::empty();
+        let mut zbuffer = f32::INFINITY;
+        for object in objects {
+            let i = object.ray_intersect(origin, direction);
+            if i.is_intersecting && i.distance < zbuffer {
+                zbuffer = i.distance;
+                intersect = i;
+            }
+        }
+        intersect
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for &count in &[0, 1, 5, 200] {
+            let objects = random_cubes(&mut rng, count);
+            let bvh = Bvh::build(&objects);
+
+            for _ in 0..2000 {
+                let (origin, direction) = random_ray(&mut rng);
+                let expected = brute_force(&objects, &origin, &direction);
+                let actual = bvh.closest_hit(&objects, &origin, &direction);
+
+                assert_eq!(expected.is_intersecting, actual.is_intersecting);
+                if expected.is_intersecting {
+                    assert_eq!(expected.distance, actual.distance);
+                    assert_eq!(expected.point, actual.point);
+                }
+
+                let max_distance = rng.gen_range(0.0..30.0);
+                let occluded = expected.is_intersecting && expected.distance < max_distance;
+                assert_eq!(occluded, bvh.any_hit(&objects, &origin, &direction, max_distance));
+            }
+        }
+    }
+
+    #[test]
+    fn containment_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let objects = random_cubes(&mut rng, 200);
+        let bvh = Bvh::build(&objects);
+
+        for _ in 0..2000 {
+            let point = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+            let expected = objects.iter().filter(|cube| cube.bounds().contains(&point)).count();
+            let mut actual = 0;
+            bvh.for_each_containing(&objects, &point, |_| actual += 1);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn refit_tracks_moved_objects() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut objects = random_cubes(&mut rng, 100);
+        let mut bvh = Bvh::build(&objects);
+
+        let offset = Vec3::new(3.0, -2.0, 1.0);
+        for cube in objects.iter_mut().step_by(3) {
+            cube.min += offset;
+            cube.max += offset;
+        }
+        bvh.refit(&objects);
+
+        for _ in 0..2000 {
+            let (origin, direction) = random_ray(&mut rng);
+            let expected = brute_force(&objects, &origin, &direction);
+            let actual = bvh.closest_hit(&objects, &origin, &direction);
+            assert_eq!(expected.is_intersecting, actual.is_intersecting);
+            if expected.is_intersecting {
+                assert_eq!(expected.distance, actual.distance);
+            }
+        }
+    }
+}
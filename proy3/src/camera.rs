@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
 use nalgebra_glm::Vec3;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Camera {
     pub eye: Vec3,    // Camera position
     pub center: Vec3, // Target the camera is looking at
@@ -1,11 +1,35 @@
 use nalgebra_glm::Vec3;
-use std::f32::INFINITY;
-use crate::{Intersect, Light, Cube, Color, ray_intersect::RayIntersect, Texture};
-use std::sync::Arc;
+use std::f32::consts::PI;
+use crate::{Intersect, Light};
+use crate::color::{Color, HdrColor};
+use crate::texture_registry::TextureRegistry;
+use crate::scene::Scene;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::material::{FresnelMode, Material};
 
 const ORIGIN_BIAS: f32 = 1e-4;
+const ENVIRONMENT_SAMPLES: u32 = 16; // Shadowed directions per hit when lit by an environment map
 
-fn offset_origin(intersect: &Intersect, direction: &Vec3) -> Vec3 {
+/// Cone of space covered by a ray, used to choose how blurry texture lookups are.
+#[derive(Debug, Clone, Copy)]
+pub struct RayCone {
+    pub width: f32,  // Width of the cone at the ray origin
+    pub spread: f32, // Growth of the width per unit of distance travelled
+}
+
+impl RayCone {
+    /// Cone of a primary ray through a pixel that subtends `pixel_angle` radians.
+    pub fn pixel(pixel_angle: f32) -> Self {
+        RayCone { width: 0.0, spread: pixel_angle }
+    }
+
+    /// Width of the cone after travelling `distance`.
+    pub fn width_at(&self, distance: f32) -> f32 {
+        self.width + self.spread * distance
+    }
+}
+
+pub(crate) fn offset_origin(intersect: &Intersect, direction: &Vec3) -> Vec3 {
     let offset = intersect.normal * ORIGIN_BIAS;
     if direction.dot(&intersect.normal) < 0.0 {
         intersect.point - offset
@@ -14,8 +38,8 @@ fn offset_origin(intersect: &Intersect, direction: &Vec3) -> Vec3 {
     }
 }
 
-fn refract(incident: &Vec3, normal: &Vec3, eta_t: f32) -> Vec3 {
-    let cosi = -incident.dot(normal).max(-1.0).min(1.0);
+pub(crate) fn refract(incident: &Vec3, normal: &Vec3, eta_t: f32) -> Vec3 {
+    let cosi = -incident.dot(normal).clamp(-1.0, 1.0);
     let (n_cosi, eta, n_normal) = if cosi < 0.0 {
         (-cosi, 1.0 / eta_t, -normal)
     } else {
@@ -29,63 +53,200 @@ fn refract(incident: &Vec3, normal: &Vec3, eta_t: f32) -> Vec3 {
     }
 }
 
-fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
+/// Weights of the reflected and refracted rays for a ray hitting `material`.
+///
+/// Without a Fresnel mode these are the material's fixed `reflectivity` and `transparency`.
+/// Otherwise transparent materials split `reflectivity + transparency` by the dielectric
+/// reflectance of `refraction_index` (all of it reflects on total internal reflection), and
+/// opaque ones use `reflectivity` as the head-on reflectance of Schlick's curve.
+pub(crate) fn specular_weights(material: &Material, incident: &Vec3, normal: &Vec3) -> (f32, f32) {
+    let (reflectivity, transparency) = (material.reflectivity, material.transparency);
+    if material.fresnel == FresnelMode::None {
+        return (reflectivity, transparency);
+    }
+
+    let cos_incidence = incident.normalize().dot(normal).clamp(-1.0, 1.0);
+    if transparency <= 0.0 {
+        let cosine = cos_incidence.abs();
+        return (reflectivity + (1.0 - reflectivity) * (1.0 - cosine).powi(5), 0.0);
+    }
+
+    let kr = match material.fresnel {
+        FresnelMode::Exact => fresnel_dielectric(cos_incidence, material.refraction_index),
+        _ => fresnel_schlick(cos_incidence, material.refraction_index),
+    };
+    let specular = reflectivity + transparency;
+    (specular * kr, specular * (1.0 - kr))
+}
+
+/// Cosines of the incident and transmitted angles, or None on total internal reflection.
+fn refraction_cosines(cos_incidence: f32, ior: f32) -> Option<(f32, f32)> {
+    // A positive cosine means the ray leaves the medium
+    let (eta_i, eta_t) = if cos_incidence > 0.0 { (ior, 1.0) } else { (1.0, ior) };
+    let cos_i = cos_incidence.abs();
+    let sin_t = eta_i / eta_t * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return None;
+    }
+    Some((cos_i, (1.0 - sin_t * sin_t).max(0.0).sqrt()))
+}
+
+fn fresnel_dielectric(cos_incidence: f32, ior: f32) -> f32 {
+    let (eta_i, eta_t) = if cos_incidence > 0.0 { (ior, 1.0) } else { (1.0, ior) };
+    match refraction_cosines(cos_incidence, ior) {
+        None => 1.0,
+        Some((cos_i, cos_t)) => {
+            let rs = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+            let rp = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+            (rs * rs + rp * rp) / 2.0
+        }
+    }
+}
+
+fn fresnel_schlick(cos_incidence: f32, ior: f32) -> f32 {
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    match refraction_cosines(cos_incidence, ior) {
+        None => 1.0,
+        Some((cos_i, cos_t)) => {
+            // Use the angle on the less dense side of the interface
+            let cosine = if cos_incidence > 0.0 && ior > 1.0 { cos_t } else { cos_i };
+            r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+        }
+    }
+}
+
+pub(crate) fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
     incident - 2.0 * incident.dot(normal) * normal
 }
 
-fn cast_shadow(intersect: &Intersect, light: &Light, objects: &[Cube]) -> f32 {
-    let light_dir = (light.position - intersect.point).normalize();
-    let shadow_ray_origin = offset_origin(intersect, &light_dir);
-    let light_distance = (light.position - shadow_ray_origin).magnitude();
-    for object in objects {
-        let shadow_intersect = object.ray_intersect(&shadow_ray_origin, &light_dir);
-        if shadow_intersect.is_intersecting && shadow_intersect.distance < light_distance {
-            return 1.0;
+/// Fraction of `light` hidden from the hit point: 0 is fully lit, 1 fully shadowed.
+///
+/// Area lights are sampled with a low-discrepancy sequence, rotated per point so the
+/// penumbra shows fine noise instead of banding.
+pub(crate) fn cast_shadow(intersect: &Intersect, light: &Light, scene: &Scene) -> f32 {
+    let samples = light.shadow_samples();
+    let (rotate_s, rotate_t) = point_hash(&intersect.point);
+    let mut blocked = 0;
+
+    for i in 0..samples {
+        let s = (rotate_s + i as f32 * 0.754_877_7).fract(); // R2 sequence
+        let t = (rotate_t + i as f32 * 0.569_840_3).fract();
+        let (light_dir, light_distance) = light.sample_direction(&intersect.point, s, t);
+        let shadow_ray_origin = offset_origin(intersect, &light_dir);
+        if scene.any_hit(&shadow_ray_origin, &light_dir, light_distance) {
+            blocked += 1;
+        }
+    }
+
+    blocked as f32 / samples as f32
+}
+
+/// Beer–Lambert attenuation of a ray refracted into `intersect`'s material, by the distance it
+/// travels inside the object; rays leaving the object are not attenuated.
+pub(crate) fn absorption(
+    intersect: &Intersect,
+    incident: &Vec3,
+    refract_origin: &Vec3,
+    refract_dir: &Vec3,
+    scene: &Scene,
+) -> HdrColor {
+    let material = &intersect.material;
+    let absorbs = material.absorption.r > 0.0 || material.absorption.g > 0.0 || material.absorption.b > 0.0;
+    if !absorbs || incident.dot(&intersect.normal) >= 0.0 {
+        return HdrColor::new(1.0, 1.0, 1.0);
+    }
+    match scene.distance_inside(refract_origin, refract_dir) {
+        Some(distance) => material.transmittance(distance),
+        None => HdrColor::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// Diffuse and glossy light from an environment map, importance sampled with shadow rays.
+fn environment_lighting(
+    intersect: &Intersect,
+    map: &EnvironmentMap,
+    ray_direction: &Vec3,
+    diffuse_color: HdrColor,
+    scene: &Scene,
+) -> HdrColor {
+    let material = &intersect.material;
+    let view_dir = -ray_direction.normalize();
+    let (rotate_s, rotate_t) = point_hash(&intersect.point);
+    let mut sum = HdrColor::black();
+
+    for i in 0..ENVIRONMENT_SAMPLES {
+        let s = (rotate_s + i as f32 * 0.754_877_7).fract();
+        let t = (rotate_t + i as f32 * 0.569_840_3).fract();
+        let (direction, radiance, pdf) = map.sample(s, t);
+        let cosine = intersect.normal.dot(&direction);
+        if cosine <= 0.0 || pdf <= 0.0 {
+            continue;
         }
+        let origin = offset_origin(intersect, &direction);
+        if scene.any_hit(&origin, &direction, f32::INFINITY) {
+            continue;
+        }
+
+        // Lambert plus normalized Phong, divided by the probability of picking this direction
+        let diffuse = diffuse_color * (material.albedo[0] / PI);
+        let reflect_dir = reflect(&-direction, &intersect.normal);
+        let gloss = material.albedo[1] * (material.spec + 2.0) / (2.0 * PI)
+            * view_dir.dot(&reflect_dir).max(0.0).powf(material.spec);
+        sum += radiance * (diffuse + HdrColor::new(gloss, gloss, gloss)) * (cosine / pdf);
     }
-    0.0
+
+    sum * (1.0 / ENVIRONMENT_SAMPLES as f32)
+}
+
+/// Two pseudo-random numbers in [0, 1) derived from a position.
+fn point_hash(point: &Vec3) -> (f32, f32) {
+    let mut h = point.x.to_bits().wrapping_mul(0x9E37_79B1)
+        ^ point.y.to_bits().wrapping_mul(0x85EB_CA77)
+        ^ point.z.to_bits().wrapping_mul(0xC2B2_AE3D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    ((h & 0xFFFF) as f32 / 65536.0, (h >> 16) as f32 / 65536.0)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cast_ray(
     ray_origin: &Vec3, 
     ray_direction: &Vec3, 
-    objects: &[Cube], 
+    scene: &Scene,
     daylight: &Light, 
     other_lights: &[Light], 
-    textures: &[Arc<Texture>],  // Added textures array here
+    textures: &TextureRegistry,
+    environment: &Environment,
+    cone: RayCone,
     depth: u32
-) -> Color {
+) -> HdrColor {
     if depth > 3 {
-        return Color::new(179, 179, 179);
+        return Color::new(179, 179, 179).into();
     }
 
-    let mut intersect = Intersect::empty();
-    let mut zbuffer = f32::INFINITY;
-
-    for object in objects {
-        let i = object.ray_intersect(&ray_origin, &ray_direction);
-        if i.is_intersecting && i.distance < zbuffer {
-            zbuffer = i.distance;
-            intersect = i;
-        }
-    }
+    let intersect = scene.closest_hit(ray_origin, ray_direction);
 
     if !intersect.is_intersecting {
-        return calculate_background_color(daylight);
+        return environment.radiance(ray_direction);
     }
 
+    // Footprint of the ray on the surface in UV units, stretched at grazing angles
+    let cone_width = cone.width_at(intersect.distance);
+    let cos_incidence = intersect.normal.dot(ray_direction).abs().max(0.1);
+    let footprint = cone_width / (cos_incidence * intersect.uv_size);
+    let diffuse_color = intersect.material.get_diffuse_color(textures, intersect.uv.0, intersect.uv.1, footprint);
+    let hit_cone = RayCone { width: cone_width, spread: cone.spread };
+
     let calculate_light_intensity = |light: &Light| {
-        let light_dir = (light.position - intersect.point).normalize();
+        let light_dir = light.direction_from(&intersect.point);
         let view_dir = (ray_origin - intersect.point).normalize();
         let reflect_dir = reflect(&-light_dir, &intersect.normal).normalize();
-        let shadow_intensity = cast_shadow(&intersect, light, objects);
+        let shadow_intensity = cast_shadow(&intersect, light, scene);
         let light_intensity = light.intensity * (1.0 - shadow_intensity);
         
         let diffuse_intensity = intersect.normal.dot(&light_dir).max(0.0);
         
-        // Now passing the `textures` array to get the diffuse color from the texture
-        let diffuse_color = intersect.material.get_diffuse_color(textures, intersect.uv.0, intersect.uv.1);
-        
         let diffuse = ((light.color * 0.09) + diffuse_color) * intersect.material.albedo[0] * diffuse_intensity * light_intensity;
 
         let specular_intensity = view_dir.dot(&reflect_dir).max(0.0).powf(intersect.material.spec);
@@ -94,42 +255,117 @@ pub fn cast_ray(
         diffuse + specular
     };
 
-    let mut total_light = calculate_light_intensity(daylight);
+    // Light coming from every direction of the hemisphere; maps are sampled so they cast shadows
+    let ambient = match environment.map() {
+        Some(map) => environment_lighting(&intersect, map, ray_direction, diffuse_color, scene),
+        None => diffuse_color * intersect.material.albedo[0] * environment.ambient(&intersect.normal),
+    };
+
+    let mut total_light = ambient + calculate_light_intensity(daylight);
     for light in other_lights {
-        total_light = total_light + calculate_light_intensity(light);
+        total_light += calculate_light_intensity(light);
     }
 
-    let reflectivity = intersect.material.reflectivity;
-    let transparency = intersect.material.transparency;
+    let (reflectivity, transparency) = specular_weights(&intersect.material, ray_direction, &intersect.normal);
 
-    let mut reflect_color = Color::black();
+    let mut reflect_color = HdrColor::black();
     if reflectivity > 0.0 {
-        let reflect_dir = reflect(&ray_direction, &intersect.normal).normalize();
+        let reflect_dir = reflect(ray_direction, &intersect.normal).normalize();
         let reflect_origin = intersect.point + intersect.normal * 0.001;
-        reflect_color = cast_ray(&reflect_origin, &reflect_dir, objects, daylight, other_lights, textures, depth + 1);  // Added textures
+        reflect_color = cast_ray(&reflect_origin, &reflect_dir, scene, daylight, other_lights, textures, environment, hit_cone, depth + 1);
     }
 
-    let mut refract_color = Color::black();
+    let mut refract_color = HdrColor::black();
     if transparency > 0.0 {
-        let refract_dir = refract(&ray_direction, &intersect.normal, intersect.material.refraction_index).normalize();
+        let refract_dir = refract(ray_direction, &intersect.normal, intersect.material.refraction_index).normalize();
         let refract_origin = offset_origin(&intersect, &refract_dir);
-        refract_color = cast_ray(&refract_origin, &refract_dir, objects, daylight, other_lights, textures, depth + 1);  // Added textures
+        refract_color = cast_ray(&refract_origin, &refract_dir, scene, daylight, other_lights, textures, environment, hit_cone, depth + 1);
+        refract_color = refract_color * absorption(&intersect, ray_direction, &refract_origin, &refract_dir, scene);
     }
 
-    total_light * (1.0 - reflectivity - transparency) + (reflect_color * reflectivity) + (refract_color * transparency)
-}
+    let mut emitted = HdrColor::black();
+    if intersect.material.is_emissive() {
+        emitted = intersect.material.get_emission(textures, intersect.uv.0, intersect.uv.1, footprint);
+    }
 
-fn calculate_background_color(daylight: &Light) -> Color {
-    let base_blue = Color::new(135, 206, 250);
-    let intensity_factor = daylight.intensity;
-    Color::new(
-        ((base_blue.r as f32 * intensity_factor).min(255.0)) as i32,
-        ((base_blue.g as f32 * intensity_factor).min(255.0)) as i32,
-        ((base_blue.b as f32 * intensity_factor).min(255.0)) as i32,
-    )
+    total_light * (1.0 - reflectivity - transparency) + (reflect_color * reflectivity) + (refract_color * transparency) + emitted
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Cube, Object};
 
+    fn glass(fresnel: FresnelMode) -> Material {
+        let mut glass = Material::new(Color::new(255, 255, 255), 50.0, [0.1, 0.1], 0.1, 0.9, 1.5);
+        glass.fresnel = fresnel;
+        glass
+    }
+
+    #[test]
+    fn fresnel_reflectance() {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let head_on = Vec3::new(0.0, 0.0, -1.0);
+        let grazing = Vec3::new(1.0, 0.0, -1e-4);
+        let r0 = ((1.5f32 - 1.0) / (1.5 + 1.0)).powi(2);
+
+        for fresnel in [FresnelMode::Schlick, FresnelMode::Exact] {
+            // Head on, entering and leaving, reflection is ((n - 1) / (n + 1))² of the specular total
+            let (reflect, refract) = specular_weights(&glass(fresnel), &head_on, &normal);
+            assert!((reflect - r0).abs() < 1e-5 && (reflect + refract - 1.0).abs() < 1e-5, "{:?}", fresnel);
+            let (reflect, _) = specular_weights(&glass(fresnel), &-head_on, &normal);
+            assert!((reflect - r0).abs() < 1e-5, "{:?}", fresnel);
+
+            // Almost everything reflects at grazing angles
+            let (reflect, refract) = specular_weights(&glass(fresnel), &grazing, &normal);
+            assert!(reflect > 0.99 && refract < 0.01, "{:?}", fresnel);
+
+            // Leaving the glass 60° off the normal is past the critical angle of about 42°
+            let leaving = Vec3::new(60f32.to_radians().sin(), 0.0, 60f32.to_radians().cos());
+            assert_eq!(specular_weights(&glass(fresnel), &leaving, &normal), (1.0, 0.0), "{:?}", fresnel);
+        }
+
+        // Opaque surfaces start from their reflectivity and rise to a mirror at grazing angles
+        let mut metal = Material::new(Color::new(200, 200, 200), 50.0, [0.5, 0.5], 0.3, 0.0, 1.0);
+        metal.fresnel = FresnelMode::Schlick;
+        assert!((specular_weights(&metal, &head_on, &normal).0 - 0.3).abs() < 1e-6);
+        assert!(specular_weights(&metal, &grazing, &normal).0 > 0.99);
+
+        // Without a mode the weights are fixed
+        assert_eq!(specular_weights(&glass(FresnelMode::None), &grazing, &normal), (0.1, 0.9));
+    }
+
+    #[test]
+    fn absorption_follows_beer_lambert() {
+        let mut glass = Material::new(Color::new(255, 255, 255), 50.0, [0.1, 0.1], 0.0, 0.9, 1.5);
+        glass.absorption = HdrColor::new(0.8, 0.3, 0.05);
+        let opaque = Material::new(Color::new(200, 200, 200), 10.0, [0.9, 0.0], 0.0, 0.0, 1.0);
+
+        // A slab 2 units thick among unrelated boxes, so the BVH has something to skip
+        let mut objects: Vec<Object> = vec![Cube::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 2.0), glass).into()];
+        for i in 0..20 {
+            let min = Vec3::new(3.0 + i as f32, 0.0, 0.0);
+            objects.push(Cube::new(min, min + Vec3::new(0.5, 0.5, 0.5), opaque.clone()).into());
+        }
+        let scene = Scene::new(objects, Vec::new());
 
+        let origin = Vec3::new(0.2, 0.1, 5.0);
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let intersect = scene.closest_hit(&origin, &direction);
+        assert!(intersect.is_intersecting);
 
+        let refract_dir = refract(&direction, &intersect.normal, intersect.material.refraction_index).normalize();
+        let refract_origin = offset_origin(&intersect, &refract_dir);
+        let attenuation = absorption(&intersect, &direction, &refract_origin, &refract_dir, &scene);
+
+        let thickness = 2.0;
+        assert!((attenuation.r - (-0.8f32 * thickness).exp()).abs() < 1e-3);
+        assert!((attenuation.g - (-0.3f32 * thickness).exp()).abs() < 1e-3);
+        assert!((attenuation.b - (-0.05f32 * thickness).exp()).abs() < 1e-3);
 
+        // Leaving the slab is not attenuated again
+        let exit = Intersect::new(Vec3::new(0.2, 0.1, 0.0), Vec3::new(0.0, 0.0, -1.0), 2.0, intersect.material.clone(), (0.0, 0.0));
+        let outside = absorption(&exit, &refract_dir, &(exit.point + refract_dir * 0.01), &refract_dir, &scene);
+        assert_eq!((outside.r, outside.g, outside.b), (1.0, 1.0, 1.0));
+    }
+}
@@ -1,6 +1,35 @@
-use std::ops::{Add, Mul};
+use once_cell::sync::Lazy;
+use std::ops::{Add, AddAssign, Mul};
 use std::fmt;
 
+/// Linear value of every 8-bit sRGB level.
+static SRGB_TO_LINEAR: Lazy<[f32; 256]> = Lazy::new(|| {
+    std::array::from_fn(|i| {
+        let c = i as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+});
+
+/// Decodes an 8-bit sRGB channel to linear light.
+pub fn srgb_to_linear(value: u8) -> f32 {
+    SRGB_TO_LINEAR[value as usize]
+}
+
+/// Encodes linear light in [0, 1] as an 8-bit sRGB channel.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Color {
     pub r: u8,
@@ -67,3 +96,119 @@ impl Add for Color {
         }
     }
 }
+
+/// Linear floating point RGB radiance, free to exceed 1.0 while light is accumulated.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct HdrColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl HdrColor {
+    pub const fn new(r: f32, g: f32, b: f32) -> HdrColor {
+        HdrColor { r, g, b }
+    }
+
+    pub const fn black() -> Self {
+        HdrColor { r: 0.0, g: 0.0, b: 0.0 }
+    }
+
+    /// Average of the three channels.
+    pub fn luminance(&self) -> f32 {
+        (self.r + self.g + self.b) / 3.0
+    }
+
+    /// Clamps to the displayable range and encodes as 8-bit sRGB.
+    pub fn to_color(self) -> Color {
+        Color {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        self.to_color().to_u32()
+    }
+}
+
+/// 8-bit colors are sRGB encoded, so converting them decodes to linear light.
+impl From<Color> for HdrColor {
+    fn from(color: Color) -> Self {
+        HdrColor {
+            r: srgb_to_linear(color.r),
+            g: srgb_to_linear(color.g),
+            b: srgb_to_linear(color.b),
+        }
+    }
+}
+
+impl fmt::Display for HdrColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HdrColor(r: {}, g: {}, b: {})", self.r, self.g, self.b)
+    }
+}
+
+impl Mul<f32> for HdrColor {
+    type Output = HdrColor;
+
+    fn mul(self, scalar: f32) -> HdrColor {
+        HdrColor {
+            r: self.r * scalar,
+            g: self.g * scalar,
+            b: self.b * scalar,
+        }
+    }
+}
+
+impl Mul for HdrColor {
+    type Output = HdrColor;
+
+    fn mul(self, other: HdrColor) -> HdrColor {
+        HdrColor {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+        }
+    }
+}
+
+impl Add for HdrColor {
+    type Output = HdrColor;
+
+    fn add(self, other: HdrColor) -> HdrColor {
+        HdrColor {
+            r: self.r + other.r,
+            g: self.g + other.g,
+            b: self.b + other.b,
+        }
+    }
+}
+
+impl AddAssign for HdrColor {
+    fn add_assign(&mut self, other: HdrColor) {
+        self.r += other.r;
+        self.g += other.g;
+        self.b += other.b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for value in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(188) - 0.5).abs() < 0.003); // Mid grey in linear light
+
+        // Out of range light is clamped, not wrapped
+        assert_eq!(linear_to_srgb(-1.0), 0);
+        assert_eq!(linear_to_srgb(7.5), 255);
+    }
+}
@@ -0,0 +1,216 @@
+use nalgebra_glm::Vec3;
+use std::f32::consts::PI;
+use crate::light::orthonormal_basis;
+use crate::object::{Face, Object};
+use crate::voxel::VoxelWorld;
+
+/// Piece of glowing surface that can be sampled uniformly by area.
+#[derive(Debug, Clone)]
+enum Patch {
+    Parallelogram { corner: Vec3, u: Vec3, v: Vec3 },
+    Triangle([Vec3; 3]),
+    Disc { center: Vec3, normal: Vec3, radius: f32 },
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl Patch {
+    /// Face of the box from `min` to `max`.
+    fn box_face(min: &Vec3, max: &Vec3, face: Face) -> Self {
+        let normal = face.normal();
+        let axis = (0..3).find(|&axis| normal[axis] != 0.0).unwrap_or(0);
+        let size = max - min;
+        let mut corner = *min;
+        if normal[axis] > 0.0 {
+            corner[axis] = max[axis];
+        }
+        let (mut u, mut v) = (Vec3::zeros(), Vec3::zeros());
+        u[(axis + 1) % 3] = size[(axis + 1) % 3];
+        v[(axis + 2) % 3] = size[(axis + 2) % 3];
+        Patch::Parallelogram { corner, u, v }
+    }
+
+    fn area(&self) -> f32 {
+        match self {
+            Patch::Parallelogram { u, v, .. } => u.cross(v).magnitude(),
+            Patch::Triangle([a, b, c]) => 0.5 * (b - a).cross(&(c - a)).magnitude(),
+            Patch::Disc { radius, .. } => PI * radius * radius,
+            Patch::Sphere { radius, .. } => 4.0 * PI * radius * radius,
+        }
+    }
+
+    /// Point for (`s`, `t`) in [0, 1)², spread evenly over the surface.
+    fn point(&self, s: f32, t: f32) -> Vec3 {
+        match self {
+            Patch::Parallelogram { corner, u, v } => corner + u * s + v * t,
+            Patch::Triangle([a, b, c]) => {
+                let root = s.sqrt();
+                a * (1.0 - root) + b * (root * (1.0 - t)) + c * (root * t)
+            }
+            Patch::Disc { center, normal, radius } => {
+                let (tangent, bitangent) = orthonormal_basis(normal);
+                let (r, phi) = (radius * s.sqrt(), 2.0 * PI * t);
+                center + tangent * (r * phi.cos()) + bitangent * (r * phi.sin())
+            }
+            Patch::Sphere { center, radius } => {
+                let z = 1.0 - 2.0 * s;
+                let (r, phi) = ((1.0 - z * z).max(0.0).sqrt(), 2.0 * PI * t);
+                center + Vec3::new(r * phi.cos(), r * phi.sin(), z) * *radius
+            }
+        }
+    }
+}
+
+/// Every glowing surface of a scene, for next-event estimation in the path tracer.
+///
+/// Points are picked uniformly over the combined area, so the density is the same everywhere
+/// and a path that hits any emissive surface can tell how likely it was to be sampled. A
+/// glowing `Plane` is infinite and cannot be sampled this way; scenes with one keep an empty
+/// set and leave all emission to the paths that hit it.
+#[derive(Debug, Clone, Default)]
+pub struct Emitters {
+    patches: Vec<Patch>,
+    cdf: Vec<f32>, // Running total of the patch areas
+}
+
+impl Emitters {
+    /// Gathers the emissive surfaces of `objects` and `voxels`.
+    pub fn collect(objects: &[Object], voxels: &[VoxelWorld]) -> Self {
+        let mut patches = Vec::new();
+        for object in objects {
+            match object {
+                Object::Cube(cube) => {
+                    for face in Face::ALL.into_iter().filter(|&face| cube.faces[face as usize].is_emissive()) {
+                        patches.push(Patch::box_face(&cube.min, &cube.max, face));
+                    }
+                }
+                Object::Sphere(sphere) if sphere.material.is_emissive() => {
+                    patches.push(Patch::Sphere { center: sphere.center, radius: sphere.radius });
+                }
+                Object::Plane(plane) if plane.material.is_emissive() => return Emitters::default(),
+                Object::Quad(quad) if quad.material.is_emissive() => {
+                    patches.push(Patch::Parallelogram { corner: quad.corner, u: quad.u, v: quad.v });
+                }
+                Object::Disc(disc) if disc.material.is_emissive() => {
+                    patches.push(Patch::Disc { center: disc.center, normal: disc.normal, radius: disc.radius });
+                }
+                Object::Triangle(triangle) if triangle.material.is_emissive() => patches.push(Patch::Triangle(triangle.vertices)),
+                Object::Mesh(mesh) => {
+                    let glowing = mesh.triangles().iter().filter(|triangle| triangle.material.is_emissive());
+                    patches.extend(glowing.map(|triangle| Patch::Triangle(triangle.vertices)));
+                }
+                _ => {}
+            }
+        }
+        for world in voxels {
+            world.for_each_emissive_face(|min, max, face| patches.push(Patch::box_face(&min, &max, face)));
+        }
+
+        patches.retain(|patch| patch.area() > 0.0);
+        let mut total = 0.0;
+        let cdf = patches.iter().map(|patch| {
+            total += patch.area();
+            total
+        }).collect();
+        Emitters { patches, cdf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Combined area of all emissive surfaces.
+    pub fn area(&self) -> f32 {
+        self.cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Picks a point on the emissive surfaces with (`pick`, `s`, `t`) in [0, 1)³, or None when
+    /// there are none. Every point has the density `1 / area()`.
+    pub fn sample(&self, pick: f32, s: f32, t: f32) -> Option<Vec3> {
+        if self.is_empty() {
+            return None;
+        }
+        let target = pick * self.area();
+        let index = self.cdf.partition_point(|&total| total <= target).min(self.patches.len() - 1);
+        Some(self.patches[index].point(s, t))
+    }
+
+    /// Density per steradian of sampling a point seen at `distance`, whose surface makes the
+    /// angle with cosine `cosine` with the direction towards it.
+    pub fn pdf(&self, distance: f32, cosine: f32) -> f32 {
+        if self.is_empty() || cosine <= 0.0 {
+            return 0.0;
+        }
+        distance * distance / (cosine * self.area())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{Color, HdrColor};
+    use crate::material::Material;
+    use crate::object::Cube;
+    use crate::primitives::{Plane, Quad, Sphere};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn glowing() -> Material {
+        let mut material = Material::new(Color::new(255, 255, 255), 10.0, [0.0, 0.0], 0.0, 0.0, 1.0);
+        material.emission = HdrColor::new(1.0, 1.0, 1.0);
+        material
+    }
+
+    fn plain() -> Material {
+        Material::new(Color::new(255, 255, 255), 10.0, [0.9, 0.0], 0.0, 0.0, 1.0)
+    }
+
+    #[test]
+    fn samples_lie_on_emissive_surfaces() {
+        let mut faces = std::array::from_fn(|_| plain());
+        faces[Face::Top as usize] = glowing();
+        let objects: Vec<Object> = vec![
+            Cube::with_faces(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 1.0, 3.0), faces).into(),
+            Sphere::new(Vec3::new(10.0, 0.0, 0.0), 0.5, glowing()).into(),
+            Quad::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), plain()).into(),
+        ];
+        let emitters = Emitters::collect(&objects, &[]);
+        assert!((emitters.area() - (6.0 + PI)).abs() < 1e-4);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut on_top = 0;
+        for _ in 0..1000 {
+            let point = emitters.sample(rng.gen(), rng.gen(), rng.gen()).unwrap();
+            if (point.y - 1.0).abs() < 1e-5 {
+                assert!((0.0..=2.0).contains(&point.x) && (0.0..=3.0).contains(&point.z));
+                on_top += 1;
+            } else {
+                assert!(((point - Vec3::new(10.0, 0.0, 0.0)).magnitude() - 0.5).abs() < 1e-4);
+            }
+        }
+        // Picked in proportion to the area: 6 of 6 + π
+        assert!((on_top as f32 / 1000.0 - 6.0 / (6.0 + PI)).abs() < 0.05);
+    }
+
+    #[test]
+    fn hidden_voxel_faces_are_skipped() {
+        let mut world = VoxelWorld::new(3, 1, 1, Vec3::zeros(), 1.0);
+        let lava = world.add_block(glowing()).unwrap();
+        let stone = world.add_block(plain()).unwrap();
+        world.set(0, 0, 0, lava);
+        world.set(1, 0, 0, lava);
+        world.set(2, 0, 0, stone);
+        // Twelve faces, minus the two touching each other and the one against stone
+        assert_eq!(Emitters::collect(&[], &[world]).area(), 9.0);
+    }
+
+    #[test]
+    fn glowing_planes_disable_sampling() {
+        let objects: Vec<Object> = vec![
+            Sphere::new(Vec3::zeros(), 1.0, glowing()).into(),
+            Plane::new(Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0), glowing()).into(),
+        ];
+        let emitters = Emitters::collect(&objects, &[]);
+        assert!(emitters.is_empty());
+        assert_eq!(emitters.sample(0.5, 0.5, 0.5), None);
+        assert_eq!(emitters.pdf(1.0, 1.0), 0.0);
+    }
+}
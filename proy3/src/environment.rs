@@ -0,0 +1,207 @@
+use image::ImageResult;
+use nalgebra_glm::Vec3;
+use std::f32::consts::PI;
+use std::path::Path;
+use crate::color::HdrColor;
+use crate::sky::{Sky, AXES};
+
+/// Equirectangular HDR image surrounding the scene, used for image-based lighting.
+///
+/// Directions are importance sampled in proportion to the brightness of each texel, so small
+/// bright features such as the sun in a capture light the scene without excessive noise.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    texels: Vec<HdrColor>,
+    pub intensity: f32, // Multiplies every texel
+    rotation: f32,      // Radians around the Y axis
+    marginal_cdf: Vec<f32>,    // Cumulative weight of the rows, ends at 1
+    conditional_cdf: Vec<f32>, // Cumulative weight of the texels in each row, each row ends at 1
+    total_weight: f32,
+    ambient: [HdrColor; 6],    // Cosine-weighted light arriving from +X, -X, +Y, -Y, +Z, -Z
+}
+
+impl EnvironmentMap {
+    /// Loads an equirectangular `.hdr` or `.exr` image (or any format `image` can read).
+    pub fn load<P: AsRef<Path>>(path: P) -> ImageResult<EnvironmentMap> {
+        let image = image::open(path)?.into_rgb32f();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let texels = image.pixels().map(|p| HdrColor::new(p[0], p[1], p[2])).collect();
+        Ok(EnvironmentMap::from_texels(width, height, texels))
+    }
+
+    /// Builds a map from linear texels stored row by row, top (+Y) row first.
+    pub fn from_texels(width: usize, height: usize, texels: Vec<HdrColor>) -> EnvironmentMap {
+        assert_eq!(texels.len(), width * height);
+
+        // Weight every texel by its brightness and the solid angle it covers
+        let mut conditional_cdf = vec![0.0; width * height];
+        let mut marginal_cdf = vec![0.0; height];
+        let mut total_weight = 0.0;
+        for y in 0..height {
+            let sin_theta = ((y as f32 + 0.5) / height as f32 * PI).sin();
+            let row = &mut conditional_cdf[y * width..(y + 1) * width];
+            let mut row_sum = 0.0;
+            for (x, cdf) in row.iter_mut().enumerate() {
+                row_sum += texels[y * width + x].luminance().max(0.0) * sin_theta;
+                *cdf = row_sum;
+            }
+            if row_sum > 0.0 {
+                row.iter_mut().for_each(|cdf| *cdf /= row_sum);
+            } else {
+                row.iter_mut().enumerate().for_each(|(x, cdf)| *cdf = (x + 1) as f32 / width as f32);
+            }
+            total_weight += row_sum;
+            marginal_cdf[y] = total_weight;
+        }
+        if total_weight > 0.0 {
+            marginal_cdf.iter_mut().for_each(|cdf| *cdf /= total_weight);
+        }
+
+        let mut map = EnvironmentMap {
+            width,
+            height,
+            texels,
+            intensity: 1.0,
+            rotation: 0.0,
+            marginal_cdf,
+            conditional_cdf,
+            total_weight,
+            ambient: [HdrColor::black(); 6],
+        };
+        map.ambient = map.integrate_ambient();
+        map
+    }
+
+    /// Turns the environment by `radians` around the Y axis.
+    pub fn set_rotation(&mut self, radians: f32) {
+        self.rotation = radians;
+        self.ambient = self.integrate_ambient();
+    }
+
+    /// Light arriving from `direction`.
+    pub fn radiance(&self, direction: &Vec3) -> HdrColor {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.texels[y * self.width + x] * self.intensity
+    }
+
+    /// Average light reaching a surface facing `normal`, weighted by the cosine.
+    pub fn ambient(&self, normal: &Vec3) -> HdrColor {
+        let n = normal.normalize();
+        let pick = |value: f32, positive: usize| if value >= 0.0 { self.ambient[positive] } else { self.ambient[positive + 1] };
+        (pick(n.x, 0) * (n.x * n.x) + pick(n.y, 2) * (n.y * n.y) + pick(n.z, 4) * (n.z * n.z)) * self.intensity
+    }
+
+    /// Picks a direction for (`s`, `t`) in [0, 1)² with density proportional to the brightness.
+    ///
+    /// Returns the direction, its radiance and the probability density per steradian.
+    pub fn sample(&self, s: f32, t: f32) -> (Vec3, HdrColor, f32) {
+        if self.total_weight <= 0.0 {
+            // Black map: nothing worth sampling
+            return (Vec3::new(0.0, 1.0, 0.0), HdrColor::black(), 0.0);
+        }
+
+        let y = self.marginal_cdf.partition_point(|&cdf| cdf < t).min(self.height - 1);
+        let row = &self.conditional_cdf[y * self.width..(y + 1) * self.width];
+        let x = row.partition_point(|&cdf| cdf < s).min(self.width - 1);
+
+        // Uniform position inside the chosen texel
+        let row_start = if y == 0 { 0.0 } else { self.marginal_cdf[y - 1] };
+        let column_start = if x == 0 { 0.0 } else { row[x - 1] };
+        let fy = ((t - row_start) / (self.marginal_cdf[y] - row_start).max(1e-8)).clamp(0.0, 1.0);
+        let fx = ((s - column_start) / (row[x] - column_start).max(1e-8)).clamp(0.0, 1.0);
+        let u = (x as f32 + fx) / self.width as f32;
+        let v = (y as f32 + fy) / self.height as f32;
+
+        let direction = self.uv_to_direction(u, v);
+        (direction, self.radiance(&direction), self.pdf(&direction))
+    }
+
+    /// Density per steradian with which `sample` returns `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f32 {
+        if self.total_weight <= 0.0 {
+            return 0.0;
+        }
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        let sin_theta = ((y as f32 + 0.5) / self.height as f32 * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let weight = self.texels[y * self.width + x].luminance().max(0.0) * sin_theta;
+        let texel_probability = weight / self.total_weight;
+        texel_probability * (self.width * self.height) as f32 / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let d = direction.normalize();
+        let phi = d.z.atan2(d.x) - self.rotation;
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = u * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+
+    fn integrate_ambient(&self) -> [HdrColor; 6] {
+        let mut ambient = [HdrColor::black(); 6];
+        let texel_angle = 2.0 * PI * PI / (self.width * self.height) as f32;
+
+        for y in 0..self.height {
+            let v = (y as f32 + 0.5) / self.height as f32;
+            let solid_angle = texel_angle * (v * PI).sin();
+            for x in 0..self.width {
+                let direction = self.uv_to_direction((x as f32 + 0.5) / self.width as f32, v);
+                let texel = self.texels[y * self.width + x];
+                for (sum, axis) in ambient.iter_mut().zip(AXES.iter()) {
+                    let cosine = axis.dot(&direction);
+                    if cosine > 0.0 {
+                        *sum += texel * (cosine * solid_angle / PI);
+                    }
+                }
+            }
+        }
+        ambient
+    }
+}
+
+/// Light surrounding the scene: the analytic sky or a captured environment map.
+#[derive(Debug, Clone)]
+pub enum Environment<'a> {
+    Sky(Sky),
+    Map(&'a EnvironmentMap),
+}
+
+impl Environment<'_> {
+    /// Light arriving from `direction`, seen by rays that leave the scene.
+    pub fn radiance(&self, direction: &Vec3) -> HdrColor {
+        match self {
+            Environment::Sky(sky) => sky.radiance(direction),
+            Environment::Map(map) => map.radiance(direction),
+        }
+    }
+
+    /// Unshadowed cosine-weighted light reaching a surface facing `normal`.
+    pub fn ambient(&self, normal: &Vec3) -> HdrColor {
+        match self {
+            Environment::Sky(sky) => sky.ambient(normal),
+            Environment::Map(map) => map.ambient(normal),
+        }
+    }
+
+    /// The environment map, when lighting should importance sample it.
+    pub fn map(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Environment::Sky(_) => None,
+            Environment::Map(map) => Some(map),
+        }
+    }
+}
@@ -1,12 +1,17 @@
-use nalgebra::Vector3;
-use crate::Color;
+use image::{ImageResult, RgbImage};
+use rayon::prelude::*;
+use std::path::Path;
+use crate::color::{Color, HdrColor};
+use crate::tonemap::ToneMapping;
 
 pub struct Framebuffer {
     pub width: usize,
     pub height: usize,
-    pub buffer: Vec<u32>,
+    pub buffer: Vec<u32>,         // Display pixels, filled by `resolve`
+    pub radiance: Vec<HdrColor>,  // Linear light written by the renderer
+    pub accumulation: Vec<HdrColor>, // Sum of the progressive samples of every pixel
+    pub samples: u32,                // Number of progressive passes in `accumulation`
     background_color: Color,
-    current_color: Color,
 }
 
 impl Framebuffer {
@@ -15,23 +20,50 @@ impl Framebuffer {
             width,
             height,
             buffer: vec![0; width * height],
+            radiance: vec![HdrColor::black(); width * height],
+            accumulation: vec![HdrColor::black(); width * height],
+            samples: 0,
             background_color: Color::new(179, 179, 179),
-            current_color: Color::new(255, 255, 255),
         }
     }
 
     pub fn clear(&mut self) {
-        let color_u32 = self.color_to_u32(&self.background_color);
+        let color_u32 = Framebuffer::color_to_u32(&self.background_color);
         self.buffer.fill(color_u32);
+        self.radiance.fill(self.background_color.into());
+    }
+
+    /// Discards the progressive samples, e.g. after the camera or a light moved.
+    pub fn reset_accumulation(&mut self) {
+        self.accumulation.fill(HdrColor::black());
+        self.samples = 0;
     }
 
     pub fn set_background_color(&mut self, color: Color) {
         self.background_color = color;
     }
 
-    fn color_to_u32(&self, color: &Color) -> u32 {
+    fn color_to_u32(color: &Color) -> u32 {
         (255u32 << 24) | ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
     }
 
+    /// Tone maps the radiance buffer into displayable sRGB pixels.
+    pub fn resolve(&mut self, tone_mapping: &ToneMapping) {
+        self.buffer
+            .par_iter_mut()
+            .zip(self.radiance.par_iter())
+            .for_each(|(pixel, radiance)| {
+                *pixel = Framebuffer::color_to_u32(&tone_mapping.apply(*radiance));
+            });
+    }
+
+    /// Writes the buffer to an image file; the format is picked from the extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let image = RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let pixel = self.buffer[y as usize * self.width + x as usize];
+            image::Rgb([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+        });
+        image.save(path)
+    }
 }
 
@@ -0,0 +1,365 @@
+//! glTF 2.0 import: meshes, materials, textures, cameras, punctual lights and node transforms.
+//!
+//! Metallic-roughness materials are approximated with the Phong-style `Material`:
+//!
+//! - the base color factor, times the base color texture if any, is the diffuse color, and
+//!   `MASK` alpha cuts out texels
+//! - metals lose their diffuse part; smooth surfaces reflect with Schlick's Fresnel, starting
+//!   at 4% for dielectrics and at the full metallic reflectance for metals
+//! - roughness sets the shininess of the highlight
+//! - `KHR_materials_transmission` and `KHR_materials_ior` make refractive glass
+//! - the emissive factor, texture and `KHR_materials_emissive_strength` give the emission
+//!
+//! The first camera in the scene becomes the view, keeping the renderer's fixed field of view;
+//! without one the camera frames the whole scene. The first directional light is the main
+//! light and the rest join `lights`; spot lights shine in every direction like point lights.
+//! Without a directional light the scene is lit by the default `DayCycle`.
+
+use nalgebra_glm::{Mat4, Vec3};
+use std::collections::HashMap;
+use std::path::Path;
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::texture::{MagFilter, WrappingMode};
+use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+use crate::bvh::{Aabb, Bounded};
+use crate::camera::Camera;
+use crate::color::HdrColor;
+use crate::light::{Light, LightShape};
+use crate::material::{AlphaCutout, FresnelMode, Material};
+use crate::mesh::{ImportError, Mesh};
+use crate::object::Object;
+use crate::primitives::Triangle;
+use crate::scene::Scene;
+use crate::scene_file::LoadedScene;
+use crate::sun::DayCycle;
+use crate::texture::{FilterMode, Texture, WrapMode};
+use crate::texture_registry::{TextureHandle, TextureRegistry};
+
+const LUX_PER_UNIT: f32 = 683.0;     // Directional lights: 1 W/m² of sunlight is one unit of intensity
+const CANDELA_PER_UNIT: f32 = 54.35; // Point lights: a 1000 W Blender lamp is one unit of intensity
+const DEFAULT_IOR: f32 = 1.5;        // glTF's index of refraction without KHR_materials_ior
+
+/// Loads the default scene of a `.gltf` or `.glb` file.
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<LoadedScene, ImportError> {
+    let path = path.as_ref();
+    let (document, buffers, images) = gltf::import(path).map_err(|e| ImportError::new(path, e.to_string()))?;
+    let gltf_scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| ImportError::new(path, "the file has no scenes"))?;
+
+    let mut importer = Importer {
+        path,
+        buffers: &buffers,
+        images: &images,
+        textures: TextureRegistry::new(),
+        texture_handles: HashMap::new(),
+        materials: HashMap::new(),
+        objects: Vec::new(),
+        camera: None,
+        daylight: None,
+        lights: Vec::new(),
+    };
+    for node in gltf_scene.nodes() {
+        importer.visit(&node, &Mat4::identity())?;
+    }
+
+    if importer.objects.is_empty() {
+        return Err(ImportError::new(path, "the scene has no triangle meshes"));
+    }
+    let camera = importer.camera.take().unwrap_or_else(|| framing_camera(&importer.objects));
+    let (daylight, sun) = match importer.daylight.take() {
+        Some(light) => (light, None),
+        None => {
+            let sun = DayCycle::new(10.0);
+            (sun.light(), Some(sun))
+        }
+    };
+
+    Ok(LoadedScene {
+        scene: Scene::new(importer.objects, Vec::new()),
+        textures: importer.textures,
+        lights: importer.lights,
+        daylight,
+        sun,
+        camera,
+    })
+}
+
+/// State shared while walking the node hierarchy.
+struct Importer<'a> {
+    path: &'a Path,
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    textures: TextureRegistry,
+    texture_handles: HashMap<usize, TextureHandle>, // By glTF image index
+    materials: HashMap<Option<usize>, Material>,   // By glTF material index, None for the default
+    objects: Vec<Object>,
+    camera: Option<Camera>,
+    daylight: Option<Light>,
+    lights: Vec<Light>,
+}
+
+impl Importer<'_> {
+    fn visit(&mut self, node: &gltf::Node, parent: &Mat4) -> Result<(), ImportError> {
+        let world = parent * Mat4::from(node.transform().matrix());
+        let position = world.transform_point(&Vec3::zeros().into()).coords;
+        let forward = world.transform_vector(&Vec3::new(0.0, 0.0, -1.0)).normalize();
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(mut mesh) = self.primitive(&primitive)? {
+                    mesh.transform(&world);
+                    self.objects.push(mesh.into());
+                }
+            }
+        }
+
+        if node.camera().is_some() && self.camera.is_none() {
+            let up = world.transform_vector(&Vec3::y()).normalize();
+            self.camera = Some(Camera::new(position, position + forward, up));
+        }
+
+        if let Some(light) = node.light() {
+            let color = HdrColor::new(light.color()[0], light.color()[1], light.color()[2]);
+            match light.kind() {
+                Kind::Directional => {
+                    let direction = -forward; // Lights shine down their local -Z
+                    let light = Light::new(position + direction * 1000.0, color, light.intensity() / LUX_PER_UNIT)
+                        .with_shape(LightShape::Directional { direction, angular_radius: 0.0 }, 1);
+                    match self.daylight {
+                        None => self.daylight = Some(light),
+                        Some(_) => self.lights.push(light),
+                    }
+                }
+                Kind::Point | Kind::Spot { .. } => {
+                    self.lights.push(Light::new(position, color, light.intensity() / CANDELA_PER_UNIT));
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.visit(&child, &world)?;
+        }
+        Ok(())
+    }
+
+    /// Triangles of one primitive in the mesh's local space; None for points and lines.
+    fn primitive(&mut self, primitive: &gltf::Primitive) -> Result<Option<Mesh>, ImportError> {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            return Ok(None);
+        }
+        let material = self.material(&primitive.material())?;
+
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions.map(Vec3::from).collect(),
+            None => return Ok(None),
+        };
+        let normals: Option<Vec<Vec3>> = reader
+            .read_normals()
+            .map(|normals| normals.map(Vec3::from).collect::<Vec<_>>())
+            .filter(|normals| normals.len() == positions.len());
+        // glTF puts the texture origin at the top left, `Texture` at the bottom left
+        let uvs: Option<Vec<(f32, f32)>> = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().map(|[u, v]| (u, 1.0 - v)).collect::<Vec<_>>())
+            .filter(|uvs| uvs.len() == positions.len());
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let mut triangles = Vec::with_capacity(indices.len() / 3);
+        for face in indices.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            if a.max(b).max(c) >= positions.len() {
+                return Err(ImportError::new(self.path, "a primitive indexes a missing vertex"));
+            }
+            let mut triangle = Triangle::new(positions[a], positions[b], positions[c], material.clone());
+            if let Some(normals) = &normals {
+                triangle = triangle.with_normals([normals[a], normals[b], normals[c]]);
+            }
+            if let Some(uvs) = &uvs {
+                triangle = triangle.with_uvs([uvs[a], uvs[b], uvs[c]]);
+            }
+            triangles.push(triangle);
+        }
+
+        Ok((!triangles.is_empty()).then(|| Mesh::new(triangles)))
+    }
+
+    fn material(&mut self, source: &gltf::Material) -> Result<Material, ImportError> {
+        if let Some(material) = self.materials.get(&source.index()) {
+            return Ok(material.clone());
+        }
+
+        let pbr = source.pbr_metallic_roughness();
+        let [r, g, b, alpha] = pbr.base_color_factor();
+        let metallic = pbr.metallic_factor();
+        let roughness = pbr.roughness_factor();
+        let smoothness = 1.0 - roughness;
+        let transmission = source.transmission().map_or(0.0, |t| t.transmission_factor());
+        let transparency = match source.alpha_mode() {
+            AlphaMode::Blend => 1.0 - alpha,
+            _ => 0.0,
+        }
+        .max(transmission);
+
+        let mut material = Material::new(
+            HdrColor::new(r, g, b).to_color(),
+            (2.0 / roughness.max(0.05).powi(4) - 2.0).clamp(1.0, 1000.0),
+            [1.0 - metallic, smoothness],
+            (0.04 + 0.96 * metallic) * smoothness,
+            transparency * (1.0 - metallic),
+            source.ior().unwrap_or(DEFAULT_IOR),
+        );
+        material.fresnel = if material.transparency > 0.0 { FresnelMode::Exact } else { FresnelMode::Schlick };
+
+        if let Some(info) = pbr.base_color_texture() {
+            let handle = self.texture(&info.texture())?;
+            material.texture = Some(handle);
+            material.tint = HdrColor::new(r, g, b); // glTF multiplies the texture by the factor
+            material.sampler.filter = match info.texture().sampler().mag_filter() {
+                Some(MagFilter::Nearest) => FilterMode::Nearest,
+                _ => FilterMode::Trilinear,
+            };
+            material.sampler.wrap = match info.texture().sampler().wrap_s() {
+                WrappingMode::ClampToEdge => WrapMode::Clamp,
+                WrappingMode::MirroredRepeat => WrapMode::Mirror,
+                WrappingMode::Repeat => WrapMode::Repeat,
+            };
+            if source.alpha_mode() == AlphaMode::Mask {
+                material.cutout = Some(AlphaCutout {
+                    threshold: source.alpha_cutoff().unwrap_or(0.5),
+                    mask: self.textures.get(handle).alpha_mask(),
+                });
+            }
+        }
+
+        let [er, eg, eb] = source.emissive_factor();
+        material.emission = HdrColor::new(er, eg, eb) * source.emissive_strength().unwrap_or(1.0);
+        if let Some(info) = source.emissive_texture() {
+            material.emission_texture = Some(self.texture(&info.texture())?);
+        }
+
+        self.materials.insert(source.index(), material.clone());
+        Ok(material)
+    }
+
+    fn texture(&mut self, texture: &gltf::Texture) -> Result<TextureHandle, ImportError> {
+        let index = texture.source().index();
+        if let Some(&handle) = self.texture_handles.get(&index) {
+            return Ok(handle);
+        }
+
+        let data = &self.images[index];
+        let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+        let image = match data.format {
+            gltf::image::Format::R8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+            gltf::image::Format::R8G8 => GrayAlphaImage::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+            gltf::image::Format::R8G8B8 => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+            gltf::image::Format::R8G8B8A8 => RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+            format => {
+                return Err(ImportError::new(self.path, format!("unsupported texture format {:?}", format)));
+            }
+        };
+        let image = image.ok_or_else(|| ImportError::new(self.path, "texture data is truncated"))?;
+
+        let handle = self.textures.insert(Texture::from_image(image));
+        self.texture_handles.insert(index, handle);
+        Ok(handle)
+    }
+}
+
+/// Camera looking at the whole scene from the front, slightly above.
+fn framing_camera(objects: &[Object]) -> Camera {
+    let bounds = objects.iter().fold(Aabb::empty(), |acc, object| acc.union(&object.bounds()));
+    let center = bounds.centroid();
+    let radius = ((bounds.max - bounds.min).magnitude() * 0.5).max(0.1);
+    let eye = center + Vec3::new(0.4, 0.5, 1.0).normalize() * radius * 2.2;
+    Camera::new(eye, center, Vec3::y())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// Writes a glTF file with one textured triangle in the z = 0 plane, a camera in front of it,
+    /// a directional light and a point light.
+    fn write_triangle(dir: &Path) -> std::path::PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        RgbImage::from_pixel(1, 1, Rgb([255, 255, 255])).save(dir.join("white.png")).unwrap();
+
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let uvs = [[0.0f32, 1.0], [1.0, 1.0], [0.0, 0.0]];
+        let bytes: Vec<u8> = positions.iter().flatten().chain(uvs.iter().flatten()).flat_map(|v| v.to_le_bytes()).collect();
+        std::fs::write(dir.join("triangle.bin"), &bytes).unwrap();
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": { "KHR_lights_punctual": { "lights": [
+                { "type": "directional", "color": [1, 1, 1], "intensity": 683 },
+                { "type": "point", "color": [1, 0.5, 0.25], "intensity": 108.7 }
+            ] } },
+            "scene": 0,
+            "scenes": [{ "nodes": [0, 1, 2, 3] }],
+            "nodes": [
+                { "mesh": 0 },
+                { "camera": 0, "translation": [0.25, 0.25, 3] },
+                { "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+                { "extensions": { "KHR_lights_punctual": { "light": 1 } }, "translation": [0, 0, 1] }
+            ],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0, "znear": 0.1 } }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "material": 0 }] }],
+            "materials": [{ "pbrMetallicRoughness": {
+                "baseColorFactor": [0.5, 0.25, 1.0, 1.0],
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 0.0
+            } }],
+            "textures": [{ "source": 0 }],
+            "images": [{ "uri": "white.png" }],
+            "buffers": [{ "byteLength": 60, "uri": "triangle.bin" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }
+            ]
+        }"#;
+        let path = dir.join("triangle.gltf");
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    #[test]
+    fn imports_triangle_scene() {
+        let path = write_triangle(&std::env::temp_dir().join(format!("proy3-gltf-{}", std::process::id())));
+        let loaded = load_gltf(&path).unwrap();
+
+        assert_eq!(loaded.scene.objects().len(), 1);
+        assert_eq!(loaded.camera.eye, Vec3::new(0.25, 0.25, 3.0));
+        assert!(loaded.sun.is_none());
+        assert!((loaded.daylight.intensity - 1.0).abs() < 1e-4);
+        assert_eq!(loaded.daylight.shape, LightShape::Directional { direction: Vec3::new(0.0, 0.0, 1.0), angular_radius: 0.0 });
+        assert_eq!(loaded.lights.len(), 1);
+        assert!((loaded.lights[0].intensity - 2.0).abs() < 1e-4);
+        assert_eq!(loaded.lights[0].position, Vec3::new(0.0, 0.0, 1.0));
+
+        // The base color factor tints the white texture
+        let hit = loaded.scene.closest_hit(&loaded.camera.eye, &Vec3::new(0.0, 0.0, -1.0));
+        assert!(hit.is_intersecting);
+        assert!((hit.distance - 3.0).abs() < 1e-4);
+        let color = hit.material.get_diffuse_color(&loaded.textures, hit.uv.0, hit.uv.1, 0.0);
+        for (actual, expected) in [(color.r, 0.5), (color.g, 0.25), (color.b, 1.0)] {
+            assert!((actual - expected).abs() < 1e-3, "{:?}", color);
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+//! Raytracer for textured block dioramas, with a Whitted integrator and a progressive path tracer.
+//!
+//! Load a scene with [`load_scene`] (or assemble a [`Scene`] by hand), then draw it into a
+//! [`Framebuffer`] with [`render`] or [`render_progressive`].
+
+pub mod antialias;
+pub mod bvh;
+pub mod camera;
+pub mod castray;
+pub mod color;
+pub mod emitter;
+pub mod environment;
+pub mod framebuffer;
+pub mod gltf_scene;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod nbt;
+pub mod obj;
+pub mod object;
+pub mod pathtrace;
+pub mod primitives;
+pub mod ray_intersect;
+pub mod render;
+pub mod scene;
+pub mod scene_file;
+pub mod schematic;
+pub mod sky;
+pub mod sun;
+pub mod texture;
+pub mod texture_registry;
+pub mod tonemap;
+pub mod vox;
+pub mod voxel;
+
+pub use antialias::{AntiAliasing, PixelFilter, SamplePattern};
+pub use camera::Camera;
+pub use castray::cast_ray;
+pub use color::{Color, HdrColor};
+pub use emitter::Emitters;
+pub use environment::{Environment, EnvironmentMap};
+pub use framebuffer::Framebuffer;
+pub use gltf_scene::load_gltf;
+pub use light::{Light, LightShape};
+pub use material::{AlphaCutout, FresnelMode, Material};
+pub use mesh::{ImportError, Mesh};
+pub use obj::load_obj;
+pub use object::{Cube, Object};
+pub use primitives::{Disc, Plane, Quad, Sphere, Triangle};
+pub use ray_intersect::{Intersect, RayIntersect};
+pub use render::{render, render_progressive};
+pub use scene::Scene;
+pub use scene_file::{load_scene, LoadedScene, SceneError};
+pub use schematic::Structure;
+pub use sky::Sky;
+pub use sun::DayCycle;
+pub use texture::{AlphaMask, Texture};
+pub use texture_registry::{TextureHandle, TextureRegistry};
+pub use tonemap::{ToneMapOperator, ToneMapping};
+pub use vox::load_vox;
+pub use voxel::{TooManyBlockTypes, VoxelWorld};
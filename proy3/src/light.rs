@@ -1,23 +1,98 @@
 use nalgebra_glm::Vec3;
-use crate::color::Color;
+use std::f32::consts::PI;
+use crate::color::HdrColor;
 
-#[derive(Debug, Clone)]
+/// Emitting surface of a light; anything but `Point` casts soft shadows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightShape {
+    Point,
+    Rectangle { u: Vec3, v: Vec3 },      // Edge vectors, centred on the light position
+    Disk { normal: Vec3, radius: f32 },
+    Sphere { radius: f32 },
+    Directional { direction: Vec3, angular_radius: f32 }, // Infinitely far away; `direction` points at the light
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Light {
     pub position: Vec3,
-    pub color: Color,
+    pub color: HdrColor, // Linear color, scaled by `intensity`
     pub intensity: f32,
+    pub shape: LightShape,
+    pub samples: u32,    // Shadow rays per shaded point for area shapes
 }
 
 impl Light {
-    pub fn new(position: Vec3, color: Color, intensity: f32) -> Self {
+    pub fn new(position: Vec3, color: impl Into<HdrColor>, intensity: f32) -> Self {
         Light {
             position,
-            color,
+            color: color.into(),
             intensity,
+            shape: LightShape::Point,
+            samples: 1,
         }
     }
 
+    /// Turns the light into an area light traced with `samples` shadow rays.
+    pub fn with_shape(mut self, shape: LightShape, samples: u32) -> Self {
+        self.shape = shape;
+        self.samples = samples.max(1);
+        self
+    }
+
     pub fn set_intensity(&mut self, intensity: f32) {
         self.intensity = intensity;
     }
-}
\ No newline at end of file
+
+    /// Number of shadow rays needed to estimate the visibility of this light.
+    pub fn shadow_samples(&self) -> u32 {
+        match self.shape {
+            LightShape::Point => 1,
+            LightShape::Directional { angular_radius, .. } if angular_radius <= 0.0 => 1,
+            _ => self.samples.max(1),
+        }
+    }
+
+    /// Unit vector from `from` towards the centre of the light.
+    pub fn direction_from(&self, from: &Vec3) -> Vec3 {
+        match self.shape {
+            LightShape::Directional { direction, .. } => direction.normalize(),
+            _ => (self.position - from).normalize(),
+        }
+    }
+
+    /// Direction and distance from `from` to the light sample (`s`, `t`) in [0, 1)².
+    pub fn sample_direction(&self, from: &Vec3, s: f32, t: f32) -> (Vec3, f32) {
+        let target = match self.shape {
+            LightShape::Point => self.position,
+            LightShape::Rectangle { u, v } => self.position + u * (s - 0.5) + v * (t - 0.5),
+            LightShape::Disk { normal, radius } => self.position + disk_offset(&normal, radius, s, t),
+            LightShape::Sphere { radius } => {
+                // A sphere looks like a disk facing the viewer
+                let normal = (from - self.position).normalize();
+                self.position + disk_offset(&normal, radius, s, t)
+            }
+            LightShape::Directional { direction, angular_radius } => {
+                let direction = direction.normalize();
+                let jittered = direction + disk_offset(&direction, angular_radius.tan(), s, t);
+                return (jittered.normalize(), f32::INFINITY);
+            }
+        };
+        let offset = target - from;
+        (offset.normalize(), offset.magnitude())
+    }
+}
+
+/// Two unit vectors that form a right-handed basis with `normal`.
+pub(crate) fn orthonormal_basis(normal: &Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+fn disk_offset(normal: &Vec3, radius: f32, s: f32, t: f32) -> Vec3 {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let r = radius * s.sqrt();
+    let phi = 2.0 * PI * t;
+    tangent * (r * phi.cos()) + bitangent * (r * phi.sin())
+}
@@ -1,34 +1,251 @@
-mod framebuffer;
-mod color;
-mod ray_intersect;
-mod material;
-mod camera;
-mod object;
-mod light;
-mod castray;
-mod texture;
-mod render;
-
-use material::{Material, TextureType};
-use minifb::{Key, Window, WindowOptions};
-use nalgebra_glm::Vec3;
+#[cfg(feature = "viewer")]
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use proy3::{
+    load_scene, render, render_progressive, AntiAliasing, Framebuffer, LoadedScene, PixelFilter, SamplePattern, ToneMapOperator,
+    ToneMapping,
+};
+#[cfg(feature = "viewer")]
+use proy3::Color;
+#[cfg(feature = "viewer")]
 use std::{f32::consts::PI, time::Duration};
-use rayon::prelude::*;
-use once_cell::sync::Lazy;
-use std::sync::Arc;
-use framebuffer::Framebuffer;
-use color::Color;
-use ray_intersect::Intersect;
-use camera::Camera;
-use object::Cube;
-use light::Light;
-use castray::cast_ray;
-use texture::Texture;
-use render::render;
+use std::{env, process};
+
+const USAGE: &str = "\
+Usage:
+  proy3 [view] [SCENE] [--width N] [--height N] [--tonemap OP] [--exposure EV] [AA OPTIONS]
+  proy3 render [SCENE] [--out FILE] [--width N] [--height N] [--tonemap OP] [--exposure EV] [AA OPTIONS]
+
+SCENE defaults to scenes/diorama.toml and FILE to frame.png.
+OP is aces (default), reinhard or clamp; EV is the exposure in stops (default 0).
+
+Anti-aliasing options:
+  --aa N                 trace N×N samples per pixel (default 1, no anti-aliasing)
+  --aa-pattern PATTERN   stratified (default) or random
+  --aa-filter FILTER     box, tent (default) or gaussian
+  --aa-adaptive T        only supersample pixels whose contrast with a neighbour exceeds T
+
+Integrator options:
+  --integrator NAME      whitted (default) or path
+  --spp N                path traced samples per pixel for `render` (default 64)
+
+  --time H               hour of the day for scenes with a [sun] section";
+
+/// Options shared by the viewer and the headless renderer.
+struct Options {
+    scene_path: String,
+    width: usize,
+    height: usize,
+    out: String,
+    tone_mapping: ToneMapping,
+    antialiasing: AntiAliasing,
+    integrator: Integrator,
+    samples_per_pixel: u32,
+    time: Option<f32>,
+}
+
+/// Light transport algorithm used to draw the scene.
+#[derive(Clone, Copy, PartialEq)]
+enum Integrator {
+    Whitted,   // Direct light plus mirror reflection and refraction, one pass per frame
+    PathTrace, // Global illumination, refined over several passes
+}
+
+enum Command {
+    View(Options),
+    Render(Options),
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("render") => parse_options(&args[1..], 1920, 1080).map(Command::Render),
+        Some("view") => parse_options(&args[1..], 800, 600).map(Command::View),
+        _ => parse_options(args, 800, 600).map(Command::View),
+    }
+}
+
+fn parse_options(args: &[String], width: usize, height: usize) -> Result<Options, String> {
+    let mut options = Options {
+        scene_path: "scenes/diorama.toml".to_string(),
+        width,
+        height,
+        out: "frame.png".to_string(),
+        tone_mapping: ToneMapping::default(),
+        antialiasing: AntiAliasing::default(),
+        integrator: Integrator::Whitted,
+        samples_per_pixel: 64,
+        time: None,
+    };
+    let mut scene_given = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().cloned().ok_or_else(|| format!("missing value for {}", name));
+        match arg.as_str() {
+            "--width" => options.width = parse_size(&value("--width")?)?,
+            "--height" => options.height = parse_size(&value("--height")?)?,
+            "--out" => options.out = value("--out")?,
+            "--tonemap" => options.tone_mapping.operator = parse_operator(&value("--tonemap")?)?,
+            "--exposure" => {
+                let exposure = value("--exposure")?;
+                options.tone_mapping.exposure = exposure.parse().map_err(|_| format!("invalid exposure {}", exposure))?;
+            }
+            "--aa" => options.antialiasing.samples = parse_size(&value("--aa")?)?,
+            "--aa-pattern" => options.antialiasing.pattern = parse_pattern(&value("--aa-pattern")?)?,
+            "--aa-filter" => options.antialiasing.filter = parse_filter(&value("--aa-filter")?)?,
+            "--aa-adaptive" => {
+                let threshold = value("--aa-adaptive")?;
+                options.antialiasing.adaptive =
+                    Some(threshold.parse().map_err(|_| format!("invalid contrast threshold {}", threshold))?);
+            }
+            "--integrator" => options.integrator = parse_integrator(&value("--integrator")?)?,
+            "--spp" => {
+                let spp = value("--spp")?;
+                options.samples_per_pixel =
+                    u32::try_from(parse_size(&spp)?).map_err(|_| format!("too many samples per pixel {}", spp))?;
+            }
+            "--time" => {
+                let time = value("--time")?;
+                options.time = Some(time.parse().map_err(|_| format!("invalid time {}", time))?);
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if scene_given => return Err(format!("unexpected argument {}", arg)),
+            _ => {
+                options.scene_path = arg.clone();
+                scene_given = true;
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("invalid size {}", value)),
+    }
+}
+
+fn parse_operator(value: &str) -> Result<ToneMapOperator, String> {
+    match value {
+        "aces" => Ok(ToneMapOperator::AcesFilmic),
+        "reinhard" => Ok(ToneMapOperator::Reinhard),
+        "clamp" => Ok(ToneMapOperator::Clamp),
+        _ => Err(format!("unknown tone mapping operator {}", value)),
+    }
+}
+
+fn parse_pattern(value: &str) -> Result<SamplePattern, String> {
+    match value {
+        "stratified" => Ok(SamplePattern::Stratified),
+        "random" => Ok(SamplePattern::Random),
+        _ => Err(format!("unknown sample pattern {}", value)),
+    }
+}
+
+fn parse_filter(value: &str) -> Result<PixelFilter, String> {
+    match value {
+        "box" => Ok(PixelFilter::Box),
+        "tent" => Ok(PixelFilter::Tent),
+        "gaussian" => Ok(PixelFilter::Gaussian),
+        _ => Err(format!("unknown filter {}", value)),
+    }
+}
+
+fn parse_integrator(value: &str) -> Result<Integrator, String> {
+    match value {
+        "whitted" => Ok(Integrator::Whitted),
+        "path" => Ok(Integrator::PathTrace),
+        _ => Err(format!("unknown integrator {}", value)),
+    }
+}
+
+/// Loads the scene, applying `--time` to its day cycle.
+fn load_or_exit(options: &Options) -> LoadedScene {
+    match load_scene(&options.scene_path) {
+        Ok(mut loaded) => {
+            if let (Some(sun), Some(time)) = (&mut loaded.sun, options.time) {
+                sun.time = time.rem_euclid(24.0);
+                loaded.daylight = sun.light();
+            }
+            loaded
+        }
+        Err(e) => {
+            eprintln!("Error loading scene: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
 fn main() {
-    let width = 800;
-    let height = 600;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match command {
+        #[cfg(feature = "viewer")]
+        Command::View(options) => run_viewer(options),
+        #[cfg(not(feature = "viewer"))]
+        Command::View(options) => {
+            eprintln!(
+                "This build has no interactive viewer; enable the `viewer` feature or run `proy3 render {}`.",
+                options.scene_path
+            );
+            process::exit(2);
+        }
+        Command::Render(options) => run_headless(options),
+    }
+}
+
+/// Renders a single frame and writes it to `options.out` without opening a window.
+fn run_headless(options: Options) {
+    let LoadedScene { scene, textures, lights, daylight, camera, .. } = load_or_exit(&options);
+
+    let mut framebuffer = Framebuffer::new(options.width, options.height);
+    match options.integrator {
+        Integrator::Whitted => {
+            render(&mut framebuffer, &scene, &camera, &lights, &daylight, &textures, &options.antialiasing)
+        }
+        Integrator::PathTrace => {
+            for _ in 0..options.samples_per_pixel {
+                render_progressive(&mut framebuffer, &scene, &camera, &lights, &daylight, &textures);
+            }
+        }
+    }
+    framebuffer.resolve(&options.tone_mapping);
+
+    if let Err(e) = framebuffer.save(&options.out) {
+        eprintln!("Error writing {}: {}", options.out, e);
+        process::exit(1);
+    }
+}
+
+/// Opens the interactive window.
+#[cfg(feature = "viewer")]
+fn run_viewer(options: Options) {
+    let width = options.width;
+    let height = options.height;
+    let mut tone_mapping = options.tone_mapping;
+    let mut integrator = options.integrator;
+
+    let LoadedScene {
+        scene,
+        textures,
+        lights: furnacelight,
+        daylight: mut mainlight,
+        mut sun,
+        mut camera,
+    } = load_or_exit(&options);
+    let mut auto_advance = false;
 
     let mut framebuffer = Framebuffer::new(width, height);
 
@@ -47,188 +264,6 @@ fn main() {
 
     framebuffer.set_background_color(Color::new(179, 179, 179));
 
-    let texture_paths = vec![
-        "./src/imagenes/ice.webp",
-        "./src/imagenes/Birch.webp",
-        "./src/imagenes/Flower.webp",
-        "./src/imagenes/Furnace_front.png",
-        "./src/imagenes/Snow_top.webp",
-        "./src/imagenes/Furnace_side.webp",
-        "./src/imagenes/Furnace_top.webp",
-    ];
-
-    // Load textures from paths
-    let textures: Vec<Arc<Texture>> = texture_paths
-        .iter()
-        .map(|path| Arc::new(Texture::new(path)))
-        .collect();
-    
-    let ice = Material::new_with_texture(
-        50.0,
-        [0.9, 0.1],
-        0.3,
-        0.2,
-        1.31,
-        TextureType::Ice
-    );
-
-    let birch = Material::new_with_texture(
-        5.0,
-        [0.7, 0.0],
-        0.1,
-        0.0,
-        1.0,
-        TextureType::Birch
-    );
-
-    let flower = Material::new_with_texture(
-        20.0,
-        [0.6, 0.2],
-        0.2,
-        0.05,
-        1.05,
-        TextureType::Flower
-    );
-
-    let snow_top = Material::new_with_texture(
-        10.0,
-        [1.0, 0.0],
-        0.05,
-        0.0,
-        1.0,
-        TextureType::Snow_top
-    );
-
-    let ffront = Material::new_with_texture(
-        100.0,
-        [0.4, 0.0],
-        0.05,
-        0.0,
-        1.0,
-        TextureType::Ffront
-    );
-
-    let fside = Material::new_with_texture(
-        100.0,
-        [0.4, 0.0],
-        0.05,
-        0.0,
-        1.0,
-        TextureType::Fside
-    );
-
-    let ftop = Material::new_with_texture(
-        100.0,
-        [0.4, 0.0],
-        0.05,
-        0.0,
-        1.0,
-        TextureType::Ftop
-    );
-
-    let snow = Material::new_with_texture(
-        10.0,
-        [1.0, 0.0],
-        0.05,
-        0.0,
-        1.0,
-        TextureType::Snow_top
-    );
-    let cube_size = 0.5;  // Tamaño del cubo
-    let mut objects = Vec::new();
-    let furnacelight = vec![
-      Light::new(Vec3::new(2.0*cube_size , -0.95, 5.4* cube_size), Color::new(220, 91, 2), 0.5), // Adjust Y to be higher
-    ];
-    
-
-    for i in 0..4 { // Número de cubos en la dirección x (4 cubos)
-        for j in 0..3 { // Número de cubos en la dirección z (3 cubos)
-            objects.push(Cube {
-                min: Vec3::new((i) as f32 * cube_size, -1.5, j as f32 * cube_size), // Vértice inferior izquierdo
-                max: Vec3::new((i) as f32 * cube_size + cube_size, -1.0, j as f32 * cube_size + cube_size), // Vértice superior derecho
-                material: snow.clone(),
-            });
-        }
-    }
-    for i in 0..2 { // Número de cubos en la dirección x (2 cubos)
-        for j in 0..3 { // Número de cubos en la dirección z (3 cubos)
-            objects.push(Cube {
-                min: Vec3::new((i + 2) as f32 * cube_size, -1.5, (j + 3) as f32 * cube_size), // Vértice inferior izquierdo
-                max: Vec3::new((i + 2) as f32 * cube_size + cube_size, -1.0, (j + 3) as f32 * cube_size + cube_size), // Vértice superior derecho
-                material: snow.clone(),
-            });
-        }
-    }
-    for i in 0..2 { // Número de cubos en la dirección x (2 cubos)
-        for j in 0..3 { // Número de cubos en la dirección z (3 cubos)
-            objects.push(Cube {
-                min: Vec3::new((i) as f32 * cube_size, -1.5, (j + 3) as f32 * cube_size), // Vértice inferior izquierdo
-                max: Vec3::new((i) as f32 * cube_size + cube_size, -1.0, (j + 3) as f32 * cube_size + cube_size), // Vértice superior derecho
-                material: ice.clone(),
-            });
-        }
-    }
-    for k in 0..3 { // Pile of 3 cubes
-        objects.push(Cube {
-            min: Vec3::new(2.0 * cube_size, -1.0 + (k as f32 * cube_size), 1.0 * cube_size), // Vértice inferior izquierdo
-            max: Vec3::new(2.0 * cube_size + cube_size, -0.5 + (k as f32 * cube_size), 1.0 * cube_size + cube_size), // Vértice superior derecho
-            material: birch.clone(), // or any material you want
-        });
-    }
-    for i in 0..3 { // Número de cubos en la dirección x (4 cubos)
-        for j in 0..3 { // Número de cubos en la dirección z (3 cubos)
-            objects.push(Cube {
-                min: Vec3::new((i+1) as f32 * cube_size, 0.5, j as f32 * cube_size), // Vértice inferior izquierdo
-                max: Vec3::new((i+1) as f32 * cube_size + cube_size, 1.0, j as f32 * cube_size + cube_size), // Vértice superior derecho
-                material: flower.clone(),
-            });
-        }
-    }
-    for j in 0..3 { // Adjusted range to only include the middle cube in z (1 cube)
-        objects.push(Cube {
-            min: Vec3::new(2.0*cube_size, 1.0, j as f32 * cube_size), // Vértice inferior izquierdo
-            max: Vec3::new(3.0*cube_size, 1.5, j as f32 * cube_size + cube_size), // Vértice superior derecho
-            material: flower.clone(),
-        });
-    }
-    objects.push(Cube {
-        min: Vec3::new(1.0*cube_size, 1.0, 1.0 * cube_size), // Vértice inferior izquierdo
-        max: Vec3::new(2.0*cube_size, 1.5, 1.0 * cube_size + cube_size), // Vértice superior derecho
-        material: flower.clone(),
-    });
-    objects.push(Cube {
-        min: Vec3::new(3.0*cube_size, 1.0, 1.0 * cube_size), // Vértice inferior izquierdo
-        max: Vec3::new(4.0*cube_size, 1.5, 1.0 * cube_size + cube_size), // Vértice superior derecho
-        material: flower.clone(),
-    });
-    objects.push(Cube {
-        min: Vec3::new(3.0*cube_size, -1.0, 5.0 * cube_size), // Vértice inferior izquierdo
-        max: Vec3::new(3.0*cube_size, -0.5, 5.0 * cube_size + cube_size), // Vértice superior derecho
-        material: ffront.clone(),
-    });
-    objects.push(Cube {
-        min: Vec3::new(3.0*cube_size, -1.0, 5.0 * cube_size), // Vértice inferior izquierdo
-        max: Vec3::new(4.0*cube_size, -0.5, 5.0 * cube_size + cube_size), // Vértice superior derecho
-        material: ftop.clone(),
-    });
-    
-
-    
-
-    let mut camera = Camera::new(
-        Vec3::new(2.0,2.0,7.0),
-        Vec3::new(1.5,1.0,0.0),
-        Vec3::new(0.0,1.0,0.0), 
-    );
-
-    let mut mainlight = Light::new(
-        Vec3::new(4.0, 5.0, 7.0),
-        Color::new(229, 156, 19),
-        0.2, // Initial intensity
-    );
-    
-    
-
     let rotaton_speed = PI/50.0;
     let zoom_speed = 0.15; 
 
@@ -238,6 +273,10 @@ fn main() {
             break;
         }
 
+        // Snapshot of what the path tracer's accumulated samples depend on: the whole camera
+        // and every light, including color changes from the day cycle
+        let before = (camera.clone(), mainlight.clone(), furnacelight.clone());
+
         if window.is_key_down(Key::W) {
             camera.orbit(0.0, -rotaton_speed);
         }
@@ -258,25 +297,74 @@ fn main() {
             camera.zoom(-zoom_speed);  
         }
 
-        // Adjust light intensity with key presses
-        if window.is_key_down(Key::Right) {
-            mainlight.set_intensity((mainlight.intensity + 0.1).min(2.0)); // Increase intensity
-        }
-        if window.is_key_down(Key::Left) {
-            mainlight.set_intensity((mainlight.intensity - 0.1).max(0.0)); // Decrease intensity
+        if let Some(sun) = &mut sun {
+            // Scrub the clock with the arrows, Space lets it run
+            if window.is_key_down(Key::Right) {
+                sun.advance(0.1);
+            }
+            if window.is_key_down(Key::Left) {
+                sun.advance(-0.1);
+            }
+            if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+                auto_advance = !auto_advance;
+            }
+            if auto_advance {
+                sun.advance(0.02);
+            }
+            mainlight = sun.light();
+        } else {
+            // Adjust light intensity with key presses
+            if window.is_key_down(Key::Right) {
+                mainlight.set_intensity((mainlight.intensity + 0.1).min(2.0)); // Increase intensity
+            }
+            if window.is_key_down(Key::Left) {
+                mainlight.set_intensity((mainlight.intensity - 0.1).max(0.0)); // Decrease intensity
+            }
         }
 
 
-        framebuffer.clear();
+        // Exposure and tone mapping operator
+        if window.is_key_down(Key::E) {
+            tone_mapping.exposure += 0.1;
+        }
+        if window.is_key_down(Key::Q) {
+            tone_mapping.exposure -= 0.1;
+        }
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            tone_mapping.operator = tone_mapping.operator.next();
+        }
+
+        // Switch between the Whitted raytracer and the path tracer
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            integrator = match integrator {
+                Integrator::Whitted => Integrator::PathTrace,
+                Integrator::PathTrace => Integrator::Whitted,
+            };
+            framebuffer.reset_accumulation();
+        }
 
-        render(
-            &mut framebuffer,
-            &objects,
-            &mut camera,
-            &furnacelight,
-            &mainlight,
-            &textures
-        );
+        match integrator {
+            Integrator::Whitted => {
+                framebuffer.clear();
+                render(
+                    &mut framebuffer,
+                    &scene,
+                    &camera,
+                    &furnacelight,
+                    &mainlight,
+                    &textures,
+                    &options.antialiasing
+                );
+            }
+            Integrator::PathTrace => {
+                // Keep accumulating while nothing moves
+                if (&camera, &mainlight, &furnacelight) != (&before.0, &before.1, &before.2) {
+                    framebuffer.reset_accumulation();
+                }
+                render_progressive(&mut framebuffer, &scene, &camera, &furnacelight, &mainlight, &textures);
+            }
+        }
+        framebuffer.resolve(&tone_mapping);
 
         window
             .update_with_buffer(&framebuffer.buffer, width, height)
@@ -1,19 +1,24 @@
-use once_cell::sync::Lazy;
+use serde::Deserialize;
 use std::sync::Arc;
+use crate::color::{Color, HdrColor};
+use crate::texture::{AlphaMask, Sampler};
+use crate::texture_registry::{TextureHandle, TextureRegistry};
 
-use crate::color::Color;
-use crate::texture::Texture;
-
+/// How the reflected and refracted parts of a surface change with the viewing angle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FresnelMode {
+    #[default]
+    None,    // Fixed `reflectivity` and `transparency` weights
+    Schlick, // Schlick's approximation, with F0 from `refraction_index`
+    Exact,   // Full dielectric Fresnel equations for unpolarized light
+}
 
+/// Alpha test that turns the texels of a cutout texture into holes rays pass through.
 #[derive(Debug, Clone)]
-pub enum TextureType {
-    Ice,
-    Birch,
-    Flower,
-    Ffront,
-    Snow_top,
-    Fside,
-    Ftop,
+pub struct AlphaCutout {
+    pub threshold: f32,      // Texels with less alpha than this are holes
+    pub mask: Arc<AlphaMask>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,8 +29,14 @@ pub struct Material {
     pub reflectivity: f32,
     pub transparency: f32,
     pub refraction_index: f32,
-    pub has_texture: bool,
-    pub texture_index: usize, // Stores the texture index
+    pub texture: Option<TextureHandle>, // Replaces `diffuse` when set
+    pub tint: HdrColor,                 // Multiplies `texture` samples, white by default
+    pub sampler: Sampler,               // Filtering and wrapping of `texture`
+    pub fresnel: FresnelMode,           // Opt-in angle dependent reflect/refract split
+    pub absorption: HdrColor,           // Beer–Lambert coefficient per unit of distance inside
+    pub emission: HdrColor,             // Light given off by the surface, linear
+    pub emission_texture: Option<TextureHandle>, // Multiplies `emission` when set
+    pub cutout: Option<AlphaCutout>,    // Lets rays through transparent texels
 }
 
 impl Material {
@@ -45,8 +56,14 @@ impl Material {
             reflectivity,
             transparency,
             refraction_index,
-            has_texture: false,
-            texture_index: 0, // Default to 0, no texture initially
+            texture: None, // No texture initially
+            tint: HdrColor::new(1.0, 1.0, 1.0),
+            sampler: Sampler::default(),
+            fresnel: FresnelMode::None,
+            absorption: HdrColor::black(),
+            emission: HdrColor::black(),
+            emission_texture: None,
+            cutout: None,
         }
     }
 
@@ -57,18 +74,8 @@ impl Material {
         reflectivity: f32,
         transparency: f32,
         refraction_index: f32,
-        texture_type: TextureType, // Only one texture type
+        texture: TextureHandle, // Handle from the scene's `TextureRegistry`
     ) -> Self {
-        let texture_index = match texture_type {
-            TextureType::Ice => 0,
-            TextureType::Birch => 1,
-            TextureType::Flower => 2,
-            TextureType::Ffront => 3,
-            TextureType::Snow_top => 4,
-            TextureType::Fside => 5,
-            TextureType::Ftop => 6,
-        };
-
         Self {
             diffuse: Color::new(0, 0, 0),
             spec,
@@ -76,20 +83,53 @@ impl Material {
             reflectivity,
             transparency,
             refraction_index,
-            has_texture: true,
-            texture_index, // Store the texture index
+            texture: Some(texture),
+            tint: HdrColor::new(1.0, 1.0, 1.0),
+            sampler: Sampler::default(),
+            fresnel: FresnelMode::None,
+            absorption: HdrColor::black(),
+            emission: HdrColor::black(),
+            emission_texture: None,
+            cutout: None,
         }
     }
 
-    // Get the color of the active texture
-    pub fn get_diffuse_color(&self, textures: &[Arc<Texture>], u: f32, v: f32) -> Color {
-        if self.has_texture {
-            let texture = &textures[self.texture_index];
-            let x = (u * (texture.width as f32 - 1.0)) as usize;
-            let y = ((1.0 - v) * (texture.height as f32 - 1.0)) as usize;
-            return texture.get_color(x, y);
+    // Get the color of the active texture; `footprint` is the size of the ray in UV units
+    pub fn get_diffuse_color(&self, textures: &TextureRegistry, u: f32, v: f32, footprint: f32) -> HdrColor {
+        if let Some(handle) = self.texture {
+            return textures.get(handle).sample(&self.sampler, u, v, footprint) * self.tint;
         }
-        self.diffuse
+        self.diffuse.into()
+    }
+
+    /// Light emitted at (`u`, `v`); black for materials that don't glow.
+    pub fn get_emission(&self, textures: &TextureRegistry, u: f32, v: f32, footprint: f32) -> HdrColor {
+        match self.emission_texture {
+            Some(handle) => textures.get(handle).sample(&self.sampler, u, v, footprint) * self.emission,
+            None => self.emission,
+        }
+    }
+
+    /// Returns true if the surface emits any light.
+    pub fn is_emissive(&self) -> bool {
+        self.emission.r > 0.0 || self.emission.g > 0.0 || self.emission.b > 0.0
+    }
+
+    /// Returns false where an alpha cutout leaves a hole at (`u`, `v`).
+    pub fn is_solid_at(&self, u: f32, v: f32) -> bool {
+        match &self.cutout {
+            Some(cutout) => cutout.mask.sample(u, v, self.sampler.wrap) >= cutout.threshold,
+            None => true,
+        }
+    }
+
+    /// Fraction of each color channel left after travelling `distance` inside the material.
+    pub fn transmittance(&self, distance: f32) -> HdrColor {
+        HdrColor::new(
+            (-self.absorption.r * distance).exp(),
+            (-self.absorption.g * distance).exp(),
+            (-self.absorption.b * distance).exp(),
+        )
     }
 
     pub fn black() -> Self {
@@ -100,8 +140,14 @@ impl Material {
             reflectivity: 0.0,
             transparency: 0.0,
             refraction_index: 0.0,
-            has_texture: false,
-            texture_index: 0, // Default to 0, no texture
+            texture: None,
+            tint: HdrColor::new(1.0, 1.0, 1.0),
+            sampler: Sampler::default(),
+            fresnel: FresnelMode::None,
+            absorption: HdrColor::black(),
+            emission: HdrColor::black(),
+            emission_texture: None,
+            cutout: None,
         }
     }
 }
@@ -0,0 +1,81 @@
+use nalgebra_glm::{Mat4, Vec3};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::bvh::{Aabb, Bounded, Bvh};
+use crate::primitives::Triangle;
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+/// Triangles loaded from a model file, with their own BVH so a mesh is a single scene object.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    triangles: Vec<Triangle>,
+    bvh: Bvh,
+    bounds: Aabb,
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let bvh = Bvh::build(&triangles);
+        let bounds = triangles.iter().fold(Aabb::empty(), |acc, triangle| acc.union(&triangle.bounds()));
+        Mesh { triangles, bvh, bounds }
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    /// Moves every vertex by `matrix` (normals by its inverse transpose) and rebuilds the BVH.
+    pub fn transform(&mut self, matrix: &Mat4) {
+        let normal_matrix = matrix.try_inverse().unwrap_or_else(Mat4::identity).transpose();
+        for triangle in &mut self.triangles {
+            triangle.vertices = triangle.vertices.map(|v| matrix.transform_point(&v.into()).coords);
+            if let Some(normals) = &mut triangle.normals {
+                *normals = normals.map(|n| normal_matrix.transform_vector(&n).normalize());
+            }
+        }
+        *self = Mesh::new(std::mem::take(&mut self.triangles));
+    }
+}
+
+impl RayIntersect for Mesh {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        self.bvh.closest_hit(&self.triangles, ray_origin, ray_direction)
+    }
+}
+
+impl Bounded for Mesh {
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+/// Placement matrix: scale, then rotate around X, Y and Z by `rotation` degrees, then translate.
+pub fn placement(position: Vec3, rotation: Vec3, scale: f32) -> Mat4 {
+    let radians = rotation.map(f32::to_radians);
+    nalgebra_glm::translation(&position)
+        * nalgebra_glm::rotation(radians.z, &Vec3::z())
+        * nalgebra_glm::rotation(radians.y, &Vec3::y())
+        * nalgebra_glm::rotation(radians.x, &Vec3::x())
+        * nalgebra_glm::scaling(&Vec3::new(scale, scale, scale))
+}
+
+/// Failure to read a model file.
+#[derive(Debug)]
+pub struct ImportError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl ImportError {
+    pub fn new(path: &Path, message: impl Into<String>) -> Self {
+        ImportError { path: path.to_path_buf(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ImportError {}
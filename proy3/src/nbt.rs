@@ -0,0 +1,326 @@
+//! Reader for Minecraft's Named Binary Tag format, plain or gzip compressed.
+
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+/// Value of one NBT tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Child `name` of a compound.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag widened to i64.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(children) => Some(children),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a whole NBT file, decompressing it first if it starts with the gzip magic.
+///
+/// Returns the name and value of the root tag.
+pub fn parse(data: &[u8]) -> io::Result<(String, Tag)> {
+    let mut decompressed = Vec::new();
+    let bytes = if data.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        &decompressed[..]
+    } else {
+        data
+    };
+
+    let mut reader = Reader { data: bytes, offset: 0 };
+    let kind = reader.u8()?;
+    let name = reader.string()?;
+    let value = reader.payload(kind, 0)?;
+    Ok((name, value))
+}
+
+const MAX_DEPTH: usize = 512; // Nesting limit, as in Minecraft itself
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self.offset.checked_add(count).filter(|&end| end <= self.data.len());
+        match end {
+            Some(end) => {
+                let bytes = &self.data[self.offset..end];
+                self.offset = end;
+                Ok(bytes)
+            }
+            None => Err(invalid("unexpected end of data")),
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    /// Element count of an array or list; every element takes at least a byte, so counts
+    /// larger than the remaining data are rejected before anything is allocated.
+    fn length(&mut self) -> io::Result<usize> {
+        let length = usize::try_from(self.i32()?).map_err(|_| invalid("negative length"))?;
+        if length > self.data.len() - self.offset {
+            return Err(invalid("length exceeds the data"));
+        }
+        Ok(length)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        // Java's modified UTF-8 only differs for NUL and supplementary characters
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+
+    fn payload(&mut self, kind: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("tags nested too deeply"));
+        }
+        Ok(match kind {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let length = self.length()?;
+                Tag::ByteArray(self.bytes(length)?.iter().map(|&b| b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item_kind = self.u8()?;
+                let length = self.length()?;
+                let items = (0..length).map(|_| self.payload(item_kind, depth + 1)).collect::<io::Result<_>>()?;
+                Tag::List(items)
+            }
+            10 => {
+                let mut children = HashMap::new();
+                loop {
+                    let child_kind = self.u8()?;
+                    if child_kind == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    children.insert(name, self.payload(child_kind, depth + 1)?);
+                }
+                Tag::Compound(children)
+            }
+            11 => {
+                let length = self.length()?;
+                Tag::IntArray((0..length).map(|_| self.i32()).collect::<io::Result<_>>()?)
+            }
+            12 => {
+                let length = self.length()?;
+                let values = (0..length).map(|_| Ok(i64::from_be_bytes(self.array()?))).collect::<io::Result<_>>()?;
+                Tag::LongArray(values)
+            }
+            _ => return Err(invalid("unknown tag type")),
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// Writes `tag` as a named root tag, the inverse of `parse` for uncompressed data.
+    pub(crate) fn encode(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut out = vec![kind(tag)];
+        string(name, &mut out);
+        payload(tag, &mut out);
+        out
+    }
+
+    /// Same as `encode`, gzip compressed like files on disk.
+    pub(crate) fn encode_gzip(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&encode(name, tag)).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn kind(tag: &Tag) -> u8 {
+        match tag {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn string(s: &str, out: &mut Vec<u8>) {
+        out.extend((s.len() as u16).to_be_bytes());
+        out.extend(s.as_bytes());
+    }
+
+    fn payload(tag: &Tag, out: &mut Vec<u8>) {
+        match tag {
+            Tag::Byte(v) => out.push(*v as u8),
+            Tag::Short(v) => out.extend(v.to_be_bytes()),
+            Tag::Int(v) => out.extend(v.to_be_bytes()),
+            Tag::Long(v) => out.extend(v.to_be_bytes()),
+            Tag::Float(v) => out.extend(v.to_be_bytes()),
+            Tag::Double(v) => out.extend(v.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                out.extend((values.len() as i32).to_be_bytes());
+                out.extend(values.iter().map(|&v| v as u8));
+            }
+            Tag::String(s) => string(s, out),
+            Tag::List(items) => {
+                out.push(items.first().map_or(0, kind));
+                out.extend((items.len() as i32).to_be_bytes());
+                items.iter().for_each(|item| payload(item, out));
+            }
+            Tag::Compound(children) => {
+                for (name, child) in children {
+                    out.push(kind(child));
+                    string(name, out);
+                    payload(child, out);
+                }
+                out.push(0);
+            }
+            Tag::IntArray(values) => {
+                out.extend((values.len() as i32).to_be_bytes());
+                values.iter().for_each(|v| out.extend(v.to_be_bytes()));
+            }
+            Tag::LongArray(values) => {
+                out.extend((values.len() as i32).to_be_bytes());
+                values.iter().for_each(|v| out.extend(v.to_be_bytes()));
+            }
+        }
+    }
+
+    pub(crate) fn compound(children: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(children.into_iter().map(|(name, tag)| (name.to_string(), tag)).collect())
+    }
+
+    fn every_kind() -> Tag {
+        compound(vec![
+            ("byte", Tag::Byte(-3)),
+            ("short", Tag::Short(-300)),
+            ("int", Tag::Int(70_000)),
+            ("long", Tag::Long(-5_000_000_000)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-0.25)),
+            ("bytes", Tag::ByteArray(vec![1, -1, 127])),
+            ("string", Tag::String("minecraft:snow_block".to_string())),
+            ("list", Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+            ("empty", Tag::List(Vec::new())),
+            ("nested", compound(vec![("inner", compound(vec![("x", Tag::Short(4))]))])),
+            ("ints", Tag::IntArray(vec![-1, 0, 1])),
+            ("longs", Tag::LongArray(vec![i64::MIN, i64::MAX])),
+        ])
+    }
+
+    #[test]
+    fn round_trips_every_tag() {
+        let tag = every_kind();
+        assert_eq!(parse(&encode("root", &tag)).unwrap(), ("root".to_string(), tag.clone()));
+        assert_eq!(parse(&encode_gzip("root", &tag)).unwrap(), ("root".to_string(), tag.clone()));
+        assert_eq!(tag.get("nested").and_then(|n| n.get("inner")).and_then(|i| i.get("x")).and_then(Tag::as_i64), Some(4));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let data = encode("root", &every_kind());
+        for length in 0..data.len() {
+            assert!(parse(&data[..length]).is_err(), "parsed {} of {} bytes", length, data.len());
+        }
+        let gzip = encode_gzip("root", &every_kind());
+        assert!(parse(&gzip[..gzip.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        // Byte array claiming more bytes than there are, and one with a negative length
+        let mut data = vec![7, 0, 0];
+        data.extend(1_000_000i32.to_be_bytes());
+        assert!(parse(&data).is_err());
+        let mut data = vec![7, 0, 0];
+        data.extend((-1i32).to_be_bytes());
+        assert!(parse(&data).is_err());
+
+        // Lists nested deeper than Minecraft allows
+        let mut data = vec![9, 0, 0];
+        for _ in 0..MAX_DEPTH + 2 {
+            data.push(9);
+            data.extend(1i32.to_be_bytes());
+        }
+        assert!(parse(&data).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Wavefront OBJ + MTL import.
+
+use nalgebra_glm::Vec3;
+use std::path::Path;
+use std::sync::Arc;
+use crate::color::{Color, HdrColor};
+use crate::material::{AlphaCutout, Material};
+use crate::mesh::{ImportError, Mesh};
+use crate::primitives::Triangle;
+use crate::texture::AlphaMask;
+use crate::texture_registry::TextureRegistry;
+
+const ALPHA_CUTOFF: f32 = 0.5; // Used when a material has an opacity map
+
+/// Loads every object of an OBJ file into one mesh.
+///
+/// Materials come from the MTL files the OBJ references, with textures loaded into `textures`
+/// relative to the OBJ. Faces without a material, or all faces when `material` is given, use
+/// `material` (a plain grey without it).
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    textures: &mut TextureRegistry,
+    material: Option<&Material>,
+) -> Result<Mesh, ImportError> {
+    let path = path.as_ref();
+    let (models, mtl) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|e| ImportError::new(path, e.to_string()))?;
+
+    let fallback = material.cloned().unwrap_or_else(|| {
+        Material::new(Color::new(180, 180, 180), 10.0, [0.9, 0.0], 0.0, 0.0, 1.0)
+    });
+    let materials = match (material, mtl) {
+        (Some(_), _) => Vec::new(),
+        (None, Ok(mtl)) => {
+            let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+            mtl.iter()
+                .map(|m| convert_material(m, base_dir, textures))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        (None, Err(e)) => return Err(ImportError::new(path, format!("cannot read materials: {}", e))),
+    };
+
+    let mut triangles = Vec::new();
+    for model in &models {
+        let mesh = &model.mesh;
+        let material = mesh.material_id.and_then(|id| materials.get(id)).unwrap_or(&fallback);
+        let position = |i: usize| Vec3::new(mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]);
+        let normal = |i: usize| Vec3::new(mesh.normals[3 * i], mesh.normals[3 * i + 1], mesh.normals[3 * i + 2]);
+        let uv = |i: usize| (mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]);
+
+        for face in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+            let mut triangle = Triangle::new(position(a), position(b), position(c), material.clone());
+            if !mesh.normals.is_empty() {
+                triangle = triangle.with_normals([normal(a), normal(b), normal(c)]);
+            }
+            if !mesh.texcoords.is_empty() {
+                triangle = triangle.with_uvs([uv(a), uv(b), uv(c)]);
+            }
+            triangles.push(triangle);
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(ImportError::new(path, "no faces found"));
+    }
+    Ok(Mesh::new(triangles))
+}
+
+/// Maps MTL parameters onto a `Material`.
+///
+/// `Kd` gives the diffuse color (multiplied into `map_Kd` when there is one), `Ks` the
+/// specular weight (and the reflectivity for the mirror illumination models 3 to 7), `Ns` the
+/// shininess, `d` the transparency, `Ni` the refraction index and `Ke` the emission.
+/// Reflectivity and transparency are scaled down together when they add up to more than 1.
+/// A `map_d` opacity map cuts holes where it is darker than 0.5.
+fn convert_material(m: &tobj::Material, base_dir: &Path, textures: &mut TextureRegistry) -> Result<Material, ImportError> {
+    let linear = |c: [f32; 3]| HdrColor::new(c[0], c[1], c[2]);
+    let diffuse = linear(m.diffuse.unwrap_or([0.8, 0.8, 0.8]));
+    let specular = m.specular.map_or(0.0, |ks| linear(ks).luminance());
+    let mirror = matches!(m.illumination_model, Some(3..=7));
+    let mut reflectivity = if mirror { specular } else { 0.0 };
+    let mut transparency = 1.0 - m.dissolve.unwrap_or(1.0).clamp(0.0, 1.0);
+    // Glass asks for both; more than all the light would leave a negative diffuse weight
+    let total = reflectivity + transparency;
+    if total > 1.0 {
+        reflectivity /= total;
+        transparency /= total;
+    }
+
+    let mut material = Material::new(
+        diffuse.to_color(),
+        m.shininess.unwrap_or(10.0).max(1.0),
+        [1.0, specular],
+        reflectivity,
+        transparency,
+        m.optical_density.unwrap_or(1.0),
+    );
+
+    if let Some(name) = &m.diffuse_texture {
+        let texture_path = base_dir.join(name);
+        let handle = textures
+            .load(&texture_path)
+            .map_err(|e| ImportError::new(&texture_path, format!("cannot load texture: {}", e)))?;
+        material.texture = Some(handle);
+        material.tint = m.diffuse.map_or(HdrColor::new(1.0, 1.0, 1.0), linear);
+    }
+
+    if let Some(name) = &m.dissolve_texture {
+        let mask_path = base_dir.join(name);
+        let mask = AlphaMask::load(&mask_path)
+            .map_err(|e| ImportError::new(&mask_path, format!("cannot load opacity map: {}", e)))?;
+        material.cutout = Some(AlphaCutout { threshold: ALPHA_CUTOFF, mask: Arc::new(mask) });
+    }
+
+    if let Some(emission) = m.unknown_param.get("Ke") {
+        let values: Vec<f32> = emission.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+        if let [r, g, b] = values[..] {
+            material.emission = HdrColor::new(r, g, b);
+        }
+    }
+    Ok(material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Rgb, RgbImage};
+    use std::path::PathBuf;
+
+    const TRIANGLE: &str = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nusemtl test\nf 1/1 2/2 3/3\n";
+
+    /// Writes a one-triangle OBJ using the material `test` of `mtl` into a fresh directory.
+    fn write_model(name: &str, mtl: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("proy3-obj-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("scene.obj"), TRIANGLE).unwrap();
+        std::fs::write(dir.join("scene.mtl"), mtl).unwrap();
+        dir
+    }
+
+    fn load_material(dir: &Path, textures: &mut TextureRegistry) -> Material {
+        let mesh = load_obj(dir.join("scene.obj"), textures, None).unwrap();
+        mesh.triangles()[0].material.clone()
+    }
+
+    #[test]
+    fn diffuse_color_tints_the_texture() {
+        let dir = write_model("tint", "newmtl test\nKd 0.5 0.25 1.0\nmap_Kd white.png\n");
+        RgbImage::from_pixel(1, 1, Rgb([255, 255, 255])).save(dir.join("white.png")).unwrap();
+        let mut textures = TextureRegistry::new();
+        let material = load_material(&dir, &mut textures);
+        let color = material.get_diffuse_color(&textures, 0.5, 0.5, 0.0);
+        assert!((color.r - 0.5).abs() < 1e-4 && (color.g - 0.25).abs() < 1e-4 && (color.b - 1.0).abs() < 1e-4);
+
+        // Without `Kd` the texture is used as it is
+        std::fs::write(dir.join("scene.mtl"), "newmtl test\nmap_Kd white.png\n").unwrap();
+        let material = load_material(&dir, &mut textures);
+        let color = material.get_diffuse_color(&textures, 0.5, 0.5, 0.0);
+        assert!((color.r - 1.0).abs() < 1e-4 && (color.g - 1.0).abs() < 1e-4 && (color.b - 1.0).abs() < 1e-4);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opacity_map_cuts_holes() {
+        // Left half transparent, right half opaque, and no diffuse texture at all
+        let dir = write_model("opacity", "newmtl test\nKd 0.5 0.5 0.5\nmap_d mask.png\n");
+        GrayImage::from_raw(2, 1, vec![0, 255]).unwrap().save(dir.join("mask.png")).unwrap();
+        let material = load_material(&dir, &mut TextureRegistry::new());
+        assert!(material.texture.is_none());
+        assert!(!material.is_solid_at(0.25, 0.5));
+        assert!(material.is_solid_at(0.75, 0.5));
+
+        std::fs::remove_file(dir.join("mask.png")).unwrap();
+        let error = load_obj(dir.join("scene.obj"), &mut TextureRegistry::new(), None).unwrap_err();
+        assert_eq!(error.path, dir.join("mask.png"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn glass_does_not_exceed_all_the_light() {
+        let dir = write_model("glass", "newmtl test\nKd 0.8 0.8 0.8\nKs 1 1 1\nd 0\nNi 1.5\nillum 7\n");
+        let material = load_material(&dir, &mut TextureRegistry::new());
+        assert!((material.reflectivity - 0.5).abs() < 1e-5);
+        assert!((material.transparency - 0.5).abs() < 1e-5);
+
+        // Within budget, both are kept as they are
+        std::fs::write(dir.join("scene.mtl"), "newmtl test\nKs 0.2 0.2 0.2\nd 0.6\nillum 3\n").unwrap();
+        let material = load_material(&dir, &mut TextureRegistry::new());
+        assert!((material.reflectivity - 0.2).abs() < 1e-5);
+        assert!((material.transparency - 0.4).abs() < 1e-5);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,138 +1,320 @@
 use nalgebra_glm::Vec3;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use crate::material::Material;
+use crate::bvh::{Aabb, Bounded};
+use crate::mesh::Mesh;
+use crate::primitives::{Disc, Plane, Quad, Sphere, Triangle};
+
+/// One of the six faces of a cube, usable as an index into `Cube::faces`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Left,   // Negative X
+    Right,  // Positive X
+    Bottom, // Negative Y
+    Top,    // Positive Y
+    Back,   // Negative Z
+    Front,  // Positive Z
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::Left, Face::Right, Face::Bottom, Face::Top, Face::Back, Face::Front];
+
+    /// Outward normal of the face.
+    pub fn normal(self) -> Vec3 {
+        match self {
+            Face::Left => Vec3::new(-1.0, 0.0, 0.0),
+            Face::Right => Vec3::new(1.0, 0.0, 0.0),
+            Face::Bottom => Vec3::new(0.0, -1.0, 0.0),
+            Face::Top => Vec3::new(0.0, 1.0, 0.0),
+            Face::Back => Vec3::new(0.0, 0.0, -1.0),
+            Face::Front => Vec3::new(0.0, 0.0, 1.0),
+        }
+    }
+}
 
 /// Structure representing a cube in 3D space.
 #[derive(Clone)]
 pub struct Cube {
     pub min: Vec3,      // Minimum point of the cube (lower-left vertex)
     pub max: Vec3,      // Maximum point of the cube (upper-right vertex)
-    pub material: Material, // Material of the cube
+    pub faces: [Material; 6], // Material of each face, indexed by `Face`
 }
 
 impl RayIntersect for Cube {
     /// Checks if a ray intersects with the cube and returns intersection details.
     fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
-        let mut tmin = (self.min.x - ray_origin.x) / ray_direction.x;
-        let mut tmax = (self.max.x - ray_origin.x) / ray_direction.x;
+        box_intersect(&self.min, &self.max, &self.faces, ray_origin, ray_direction)
+    }
+}
 
-        if tmin > tmax {
-            std::mem::swap(&mut tmin, &mut tmax);
+impl Bounded for Cube {
+    fn bounds(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+}
+
+impl Cube {
+    /// Creates a cube with the same material on every face.
+    pub fn new(min: Vec3, max: Vec3, material: Material) -> Self {
+        Cube {
+            min,
+            max,
+            faces: std::array::from_fn(|_| material.clone()),
         }
+    }
 
-        let mut tymin = (self.min.y - ray_origin.y) / ray_direction.y;
-        let mut tymax = (self.max.y - ray_origin.y) / ray_direction.y;
+    /// Creates a cube with one material per face, indexed by `Face`.
+    pub fn with_faces(min: Vec3, max: Vec3, faces: [Material; 6]) -> Self {
+        Cube { min, max, faces }
+    }
 
-        if tymin > tymax {
-            std::mem::swap(&mut tymin, &mut tymax);
+    /// Distance a ray starting inside the cube travels before leaving it, or None from outside.
+    pub fn exit_distance(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+        let bounds = self.bounds();
+        if !bounds.contains(ray_origin) {
+            return None;
         }
+        let inv_direction = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        Some(bounds.exit_distance(ray_origin, &inv_direction))
+    }
+}
 
-        if (tmin > tymax) || (tymin > tmax) {
-            return Intersect::empty(); // No intersection
-        }
+/// Intersects the box `min..max` whose faces use `faces`, cloning only the material that was hit.
+///
+/// Shared by `Cube` and the voxels of a `VoxelWorld`, which have no `Cube` of their own.
+pub(crate) fn box_intersect(min: &Vec3, max: &Vec3, faces: &[Material; 6], ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+    let mut tmin = (min.x - ray_origin.x) / ray_direction.x;
+    let mut tmax = (max.x - ray_origin.x) / ray_direction.x;
 
-        if tymin > tmin {
-            tmin = tymin;
-        }
+    if tmin > tmax {
+        std::mem::swap(&mut tmin, &mut tmax);
+    }
+
+    let mut tymin = (min.y - ray_origin.y) / ray_direction.y;
+    let mut tymax = (max.y - ray_origin.y) / ray_direction.y;
 
-        if tymax < tmax {
-            tmax = tymax;
+    if tymin > tymax {
+        std::mem::swap(&mut tymin, &mut tymax);
+    }
+
+    if (tmin > tymax) || (tymin > tmax) {
+        return Intersect::empty(); // No intersection
+    }
+
+    if tymin > tmin {
+        tmin = tymin;
+    }
+
+    if tymax < tmax {
+        tmax = tymax;
+    }
+
+    let mut tzmin = (min.z - ray_origin.z) / ray_direction.z;
+    let mut tzmax = (max.z - ray_origin.z) / ray_direction.z;
+
+    if tzmin > tzmax {
+        std::mem::swap(&mut tzmin, &mut tzmax);
+    }
+
+    if (tmin > tzmax) || (tzmin > tmax) {
+        return Intersect::empty(); // No intersection
+    }
+
+    if tzmin > tmin {
+        tmin = tzmin;
+    }
+
+    if tzmax < tmax {
+        tmax = tzmax;
+    }
+
+    // If tmin is positive, there's an intersection in the direction of the ray
+    if tmin > 0.0 {
+        if let Some(intersect) = surface_hit(min, max, faces, ray_origin, ray_direction, tmin, false) {
+            return intersect;
+        }
+        // Seen through a hole in the near face, the inside of the far face may be solid
+        if let Some(intersect) = surface_hit(min, max, faces, ray_origin, ray_direction, tmax, true) {
+            return intersect;
         }
+    }
+
+    Intersect::empty() // No valid intersection
+}
+
+/// Hit at distance `t` along the ray, or None if the face is a cutout hole there.
+///
+/// `inside` marks a face seen from within the box, whose normal is flipped towards the ray.
+fn surface_hit(
+    min: &Vec3,
+    max: &Vec3,
+    faces: &[Material; 6],
+    ray_origin: &Vec3,
+    ray_direction: &Vec3,
+    t: f32,
+    inside: bool,
+) -> Option<Intersect> {
+    let point = ray_origin + ray_direction * t;
+    let face = calculate_face(min, max, &point); // Face that was hit, which gives the normal
+    let material = &faces[face as usize];
+
+    // Calculate UV coordinates
+    let (u, v) = calculate_uv(min, max, &point, face);
+    if !material.is_solid_at(u, v) {
+        return None;
+    }
+
+    let normal = if inside { -face.normal() } else { face.normal() };
+    let mut intersect = Intersect::new(point, normal, t, material.clone(), (u, v));
+    intersect.uv_size = face_size(min, max, face);
+    Some(intersect)
+}
 
-        let mut tzmin = (self.min.z - ray_origin.z) / ray_direction.z;
-        let mut tzmax = (self.max.z - ray_origin.z) / ray_direction.z;
+/// Finds the face of the box the intersection point lies on.
+fn calculate_face(min: &Vec3, max: &Vec3, point: &Vec3) -> Face {
+    let epsilon = 1e-4; // Small value for precision
 
-        if tzmin > tzmax {
-            std::mem::swap(&mut tzmin, &mut tzmax);
+    if (point.x - min.x).abs() < epsilon {
+        Face::Left
+    } else if (point.x - max.x).abs() < epsilon {
+        Face::Right
+    } else if (point.y - min.y).abs() < epsilon {
+        Face::Bottom
+    } else if (point.y - max.y).abs() < epsilon {
+        Face::Top
+    } else if (point.z - min.z).abs() < epsilon {
+        Face::Back
+    } else {
+        Face::Front
+    }
+}
+
+/// Largest edge of a face, which is the world size its texture is stretched over.
+fn face_size(min: &Vec3, max: &Vec3, face: Face) -> f32 {
+    let extent = max - min;
+    match face {
+        Face::Left | Face::Right => extent.z.max(extent.y),
+        Face::Bottom | Face::Top => extent.x.max(extent.z),
+        Face::Back | Face::Front => extent.x.max(extent.y),
+    }
+}
+
+/// Calculates the UV texture coordinates at the intersection point.
+fn calculate_uv(min: &Vec3, max: &Vec3, point: &Vec3, face: Face) -> (f32, f32) {
+    match face {
+        Face::Left | Face::Right => {
+            let u = (point.z - min.z) / (max.z - min.z);
+            let v = (max.y - point.y) / (max.y - min.y);
+            (u, v)
+        }
+        Face::Bottom | Face::Top => {
+            let u = (point.x - min.x) / (max.x - min.x);
+            let v = (point.z - min.z) / (max.z - min.z);
+            (u, v)
+        }
+        Face::Back => {
+            let u = (max.x - point.x) / (max.x - min.x);
+            let v = (max.y - point.y) / (max.y - min.y);
+            (u, v)
         }
+        Face::Front => {
+            let u = (point.x - min.x) / (max.x - min.x);
+            let v = (max.y - point.y) / (max.y - min.y);
+            (u, v)
+        }
+    }
+}
 
-        if (tmin > tzmax) || (tzmin > tmax) {
-            return Intersect::empty(); // No intersection
+/// Any shape a `Scene` can hold, so cubes and primitives share one BVH.
+#[derive(Clone)]
+pub enum Object {
+    Cube(Box<Cube>), // Boxed, six face materials would make every object large
+    Sphere(Sphere),
+    Plane(Plane),
+    Quad(Quad),
+    Disc(Disc),
+    Triangle(Triangle),
+    Mesh(Mesh),
+}
+
+impl Object {
+    /// Distance a ray starting inside a solid object travels before leaving it; None from
+    /// outside and for flat shapes.
+    pub fn exit_distance(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+        match self {
+            Object::Cube(cube) => cube.exit_distance(ray_origin, ray_direction),
+            Object::Sphere(sphere) => sphere.exit_distance(ray_origin, ray_direction),
+            Object::Plane(_) | Object::Quad(_) | Object::Disc(_) | Object::Triangle(_) | Object::Mesh(_) => None,
         }
+    }
+}
 
-        if tzmin > tmin {
-            tmin = tzmin;
+impl RayIntersect for Object {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        match self {
+            Object::Cube(cube) => cube.ray_intersect(ray_origin, ray_direction),
+            Object::Sphere(sphere) => sphere.ray_intersect(ray_origin, ray_direction),
+            Object::Plane(plane) => plane.ray_intersect(ray_origin, ray_direction),
+            Object::Quad(quad) => quad.ray_intersect(ray_origin, ray_direction),
+            Object::Disc(disc) => disc.ray_intersect(ray_origin, ray_direction),
+            Object::Triangle(triangle) => triangle.ray_intersect(ray_origin, ray_direction),
+            Object::Mesh(mesh) => mesh.ray_intersect(ray_origin, ray_direction),
         }
+    }
+}
 
-        if tzmax < tmax {
-            tmax = tzmax;
+impl Bounded for Object {
+    fn bounds(&self) -> Aabb {
+        match self {
+            Object::Cube(cube) => cube.bounds(),
+            Object::Sphere(sphere) => sphere.bounds(),
+            Object::Plane(plane) => plane.bounds(),
+            Object::Quad(quad) => quad.bounds(),
+            Object::Disc(disc) => disc.bounds(),
+            Object::Triangle(triangle) => triangle.bounds(),
+            Object::Mesh(mesh) => mesh.bounds(),
         }
+    }
+}
 
-        // If tmin is positive, there's an intersection in the direction of the ray
-        if tmin > 0.0 {
-            let point = ray_origin + ray_direction * tmin;
-            let normal = self.calculate_normal(&point); // Calculate the normal at the intersection
-            let distance = tmin;
+impl From<Cube> for Object {
+    fn from(cube: Cube) -> Self {
+        Object::Cube(Box::new(cube))
+    }
+}
 
-            // Calculate UV coordinates
-            let (u, v) = self.calculate_uv(&point);
-            
-            return Intersect::new(point, normal, distance, self.material.clone(), (u, v));
-        }
+impl From<Sphere> for Object {
+    fn from(sphere: Sphere) -> Self {
+        Object::Sphere(sphere)
+    }
+}
 
-        Intersect::empty() // No valid intersection
+impl From<Plane> for Object {
+    fn from(plane: Plane) -> Self {
+        Object::Plane(plane)
     }
 }
 
-impl Cube {
-    /// Calculates the normal at the intersection point.
-    fn calculate_normal(&self, point: &Vec3) -> Vec3 {
-        let epsilon = 1e-4; // Small value for precision
-
-        if (point.x - self.min.x).abs() < epsilon {
-            return Vec3::new(-1.0, 0.0, 0.0); // Left face
-        } else if (point.x - self.max.x).abs() < epsilon {
-            return Vec3::new(1.0, 0.0, 0.0); // Right face
-        } else if (point.y - self.min.y).abs() < epsilon {
-            return Vec3::new(0.0, -1.0, 0.0); // Bottom face
-        } else if (point.y - self.max.y).abs() < epsilon {
-            return Vec3::new(0.0, 1.0, 0.0); // Top face
-        } else if (point.z - self.min.z).abs() < epsilon {
-            return Vec3::new(0.0, 0.0, -1.0); // Back face
-        } else if (point.z - self.max.z).abs() < epsilon {
-            return Vec3::new(0.0, 0.0, 1.0); // Front face
-        }
+impl From<Quad> for Object {
+    fn from(quad: Quad) -> Self {
+        Object::Quad(quad)
+    }
+}
 
-        Vec3::new(0.0, 0.0, 0.0) // Default normal if no face matches
-    }
-
-    /// Calculates the UV texture coordinates at the intersection point.
-    fn calculate_uv(&self, point: &Vec3) -> (f32, f32) {
-        let epsilon = 1e-4;
-
-        if (point.x - self.min.x).abs() < epsilon {
-            // Left face (negative X axis)
-            let u = (point.z - self.min.z) / (self.max.z - self.min.z);
-            let v = (self.max.y - point.y) / (self.max.y - self.min.y); 
-            return (u, v);
-        } else if (point.x - self.max.x).abs() < epsilon {
-            // Right face (positive X axis)
-            let u = (point.z - self.min.z) / (self.max.z - self.min.z);
-            let v = (self.max.y - point.y) / (self.max.y - self.min.y); 
-            return (u, v);
-        } else if (point.y - self.min.y).abs() < epsilon {
-            // Bottom face (negative Y axis)
-            let u = (point.x - self.min.x) / (self.max.x - self.min.x);
-            let v = (point.z - self.min.z) / (self.max.z - self.min.z);
-            return (u, v);
-        } else if (point.y - self.max.y).abs() < epsilon {
-            // Top face (positive Y axis)
-            let u = (point.x - self.min.x) / (self.max.x - self.min.x);
-            let v = (point.z - self.min.z) / (self.max.z - self.min.z);
-            return (u, v);
-        } else if (point.z - self.min.z).abs() < epsilon {
-            // Back face (negative Z axis)
-            let u = (self.max.x - point.x) / (self.max.x - self.min.x);
-            let v = (self.max.y - point.y) / (self.max.y - self.min.y);
-            return (u, v);
-        } else {
-            // Front face (positive Z axis)
-            let u = (point.x - self.min.x) / (self.max.x - self.min.x);
-            let v = (self.max.y - point.y) / (self.max.y - self.min.y);
-            return (u, v);
-        }
+impl From<Disc> for Object {
+    fn from(disc: Disc) -> Self {
+        Object::Disc(disc)
+    }
+}
 
-        (0.0, 0.0) // Default UV if no face is matched
+impl From<Triangle> for Object {
+    fn from(triangle: Triangle) -> Self {
+        Object::Triangle(triangle)
     }
 }
 
+impl From<Mesh> for Object {
+    fn from(mesh: Mesh) -> Self {
+        Object::Mesh(mesh)
+    }
+}
@@ -0,0 +1,222 @@
+use nalgebra_glm::Vec3;
+use rand::Rng;
+use std::f32::consts::PI;
+use crate::castray::{absorption, cast_shadow, offset_origin, reflect, refract, specular_weights, RayCone};
+use crate::color::HdrColor;
+use crate::light::{orthonormal_basis, Light};
+use crate::scene::Scene;
+use crate::environment::Environment;
+use crate::texture_registry::TextureRegistry;
+
+const MAX_BOUNCES: u32 = 16;      // Hard limit, Russian roulette usually stops paths much earlier
+const ROULETTE_START: u32 = 3;    // Bounces traced before paths may be terminated
+const EMITTER_TOLERANCE: f32 = 1e-3; // Relative distance within which a shadow ray reaches an emitter sample
+
+/// Estimates the light arriving along one camera ray with a unidirectional path tracer.
+///
+/// Diffuse bounces sample the cosine-weighted hemisphere and gather direct light from every
+/// `Light` at each vertex (next-event estimation); paths that escape pick up the environment,
+/// which is also sampled directly when it is an image, and emissive surfaces are sampled
+/// directly too. Both are balanced against hits of the bounce with multiple importance
+/// sampling. Lights use the same falloff-free model as `cast_ray`, so both integrators agree
+/// on brightness.
+#[allow(clippy::too_many_arguments)]
+pub fn trace_path<R: Rng>(
+    ray_origin: &Vec3,
+    ray_direction: &Vec3,
+    scene: &Scene,
+    daylight: &Light,
+    other_lights: &[Light],
+    textures: &TextureRegistry,
+    environment: &Environment,
+    cone: RayCone,
+    rng: &mut R,
+) -> HdrColor {
+    let mut origin = *ray_origin;
+    let mut direction = *ray_direction;
+    let mut cone = cone;
+    let mut throughput = HdrColor::new(1.0, 1.0, 1.0);
+    let mut radiance = HdrColor::black();
+    let mut bounce_pdf: Option<f32> = None; // Density of the last diffuse bounce, for MIS
+
+    for bounce in 0..MAX_BOUNCES {
+        let intersect = scene.closest_hit(&origin, &direction);
+        if !intersect.is_intersecting {
+            let light = throughput * environment.radiance(&direction);
+            // Balance against the environment samples taken at the previous vertex
+            let weight = match (environment.map(), bounce_pdf) {
+                (Some(map), Some(pdf)) => power_heuristic(pdf, map.pdf(&direction)),
+                _ => 1.0,
+            };
+            radiance += light * weight;
+            break;
+        }
+
+        let material = &intersect.material;
+        let cone_width = cone.width_at(intersect.distance);
+        let cos_incidence = intersect.normal.dot(&direction).abs().max(0.1);
+        let footprint = cone_width / (cos_incidence * intersect.uv_size);
+        let base_color = material.get_diffuse_color(textures, intersect.uv.0, intersect.uv.1, footprint);
+        cone = RayCone { width: cone_width, spread: cone.spread };
+
+        // Balance against the emitter samples taken at the previous vertex
+        if material.is_emissive() {
+            let emission = material.get_emission(textures, intersect.uv.0, intersect.uv.1, footprint);
+            let weight = match bounce_pdf {
+                Some(pdf) => power_heuristic(pdf, scene.emitters().pdf(intersect.distance, intersect.normal.dot(&direction).abs())),
+                None => 1.0,
+            };
+            radiance += throughput * emission * weight;
+        }
+
+        // Pick one lobe with probability equal to its weight, so no extra scaling is needed
+        let (reflectivity, transparency) = specular_weights(material, &direction, &intersect.normal);
+        let lobe = rng.gen::<f32>();
+        if lobe < reflectivity {
+            direction = reflect(&direction, &intersect.normal).normalize();
+            origin = offset_origin(&intersect, &direction);
+            bounce_pdf = None;
+        } else if lobe < reflectivity + transparency {
+            let incident = direction;
+            direction = refract(&direction, &intersect.normal, material.refraction_index).normalize();
+            origin = offset_origin(&intersect, &direction);
+            throughput = throughput * absorption(&intersect, &incident, &origin, &direction, scene);
+            bounce_pdf = None;
+        } else {
+            // Face the normal towards the incoming ray so back faces shade too
+            let normal = if intersect.normal.dot(&direction) > 0.0 { -intersect.normal } else { intersect.normal };
+            let view_dir = -direction;
+
+            // Next-event estimation
+            for light in std::iter::once(daylight).chain(other_lights) {
+                let light_dir = light.direction_from(&intersect.point);
+                let ndotl = normal.dot(&light_dir);
+                if ndotl <= 0.0 {
+                    continue;
+                }
+                let visibility = 1.0 - cast_shadow(&intersect, light, scene);
+                if visibility <= 0.0 {
+                    continue;
+                }
+                let light_radiance = light.color * (light.intensity * visibility);
+                let diffuse = base_color * material.albedo[0] * ndotl;
+                let reflect_dir = reflect(&-light_dir, &normal).normalize();
+                let specular_intensity = view_dir.dot(&reflect_dir).max(0.0).powf(material.spec);
+                let specular = material.albedo[1] * specular_intensity;
+                radiance += throughput * light_radiance * (diffuse + HdrColor::new(specular, specular, specular));
+            }
+
+            // Importance sampled environment light, weighted against the bounce below
+            if let Some(map) = environment.map() {
+                let (light_dir, light, light_pdf) = map.sample(rng.gen(), rng.gen());
+                let cosine = normal.dot(&light_dir);
+                if cosine > 0.0 && light_pdf > 0.0 {
+                    let shadow_origin = offset_origin(&intersect, &light_dir);
+                    if !scene.any_hit(&shadow_origin, &light_dir, f32::INFINITY) {
+                        let bsdf_pdf = cosine / PI;
+                        let weight = power_heuristic(light_pdf, bsdf_pdf);
+                        radiance += throughput * base_color * light * (material.albedo[0] * bsdf_pdf / light_pdf * weight);
+                    }
+                }
+            }
+
+            // Point on a glowing surface, weighted against the bounce below. The shadow ray has
+            // to land on that point, and the surface it hits gives the emission there
+            if let Some(point) = scene.emitters().sample(rng.gen(), rng.gen(), rng.gen()) {
+                let distance = (point - intersect.point).magnitude();
+                let light_dir = (point - intersect.point) / distance;
+                let cosine = normal.dot(&light_dir);
+                if cosine > 0.0 {
+                    let shadow_origin = offset_origin(&intersect, &light_dir);
+                    let hit = scene.closest_hit(&shadow_origin, &light_dir);
+                    let reached = hit.is_intersecting && (hit.point - point).magnitude() <= EMITTER_TOLERANCE * distance.max(1.0);
+                    let light_pdf = scene.emitters().pdf(distance, hit.normal.dot(&light_dir).abs());
+                    if reached && hit.material.is_emissive() && light_pdf > 0.0 {
+                        let light = hit.material.get_emission(textures, hit.uv.0, hit.uv.1, 0.0);
+                        let bsdf_pdf = cosine / PI;
+                        let weight = power_heuristic(light_pdf, bsdf_pdf);
+                        radiance += throughput * base_color * light * (material.albedo[0] * bsdf_pdf / light_pdf * weight);
+                    }
+                }
+            }
+
+            // Cosine-weighted bounce: the cosine and pdf cancel, leaving the albedo
+            throughput = throughput * base_color * material.albedo[0];
+            direction = cosine_hemisphere(&normal, rng);
+            origin = offset_origin(&intersect, &direction);
+            bounce_pdf = Some(normal.dot(&direction).max(0.0) / PI);
+        }
+
+        // Russian roulette
+        if bounce >= ROULETTE_START {
+            let survival = throughput.r.max(throughput.g).max(throughput.b).clamp(0.05, 0.95);
+            if rng.gen::<f32>() > survival {
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+    }
+
+    radiance
+}
+
+/// Random direction around `normal` with density proportional to the cosine.
+fn cosine_hemisphere<R: Rng>(normal: &Vec3, rng: &mut R) -> Vec3 {
+    let r1 = rng.gen::<f32>();
+    let r2 = rng.gen::<f32>();
+    let phi = 2.0 * PI * r1;
+    let radius = r2.sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+
+    (tangent * (radius * phi.cos()) + bitangent * (radius * phi.sin()) + normal * (1.0 - r2).sqrt()).normalize()
+}
+
+/// Multiple importance sampling weight of a strategy with density `pdf` against `other_pdf`.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::environment::EnvironmentMap;
+    use crate::material::Material;
+    use crate::object::Object;
+    use crate::primitives::{Disc, Quad};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn glowing_disc_lights_the_floor() {
+        // A white floor under a disc of radius 1 at height 1, in a black world
+        let floor = Material::new(Color::new(255, 255, 255), 10.0, [0.8, 0.0], 0.0, 0.0, 1.0);
+        let mut lamp = Material::new(Color::new(0, 0, 0), 10.0, [0.0, 0.0], 0.0, 0.0, 1.0);
+        lamp.emission = HdrColor::new(2.0, 2.0, 2.0);
+        let objects: Vec<Object> = vec![
+            Quad::new(Vec3::new(-50.0, 0.0, -50.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 100.0), floor).into(),
+            Disc::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 1.0, lamp).into(),
+        ];
+        let scene = Scene::new(objects, Vec::new());
+        let black = EnvironmentMap::from_texels(1, 1, vec![HdrColor::black()]);
+        let environment = Environment::Map(&black);
+        let daylight = Light::new(Vec3::new(0.0, 10.0, 0.0), Color::new(255, 255, 255), 0.0);
+        let textures = TextureRegistry::new();
+
+        // Seen from the side, below the disc, at its center
+        let origin = Vec3::new(3.0, 0.5, 0.0);
+        let direction = (-origin).normalize();
+        let mut rng = StdRng::seed_from_u64(7);
+        let samples = 4000;
+        let mut sum = HdrColor::black();
+        for _ in 0..samples {
+            sum += trace_path(&origin, &direction, &scene, &daylight, &[], &textures, &environment, RayCone::pixel(0.0), &mut rng);
+        }
+        let estimate = sum.r / samples as f32;
+
+        // Irradiance under a disc is π L R² / (h² + R²), reflected by a Lambertian albedo
+        let expected = 0.8 * 2.0 * 1.0 / (1.0 + 1.0);
+        assert!((estimate - expected).abs() < 0.03 * expected, "{estimate} vs {expected}");
+    }
+}
@@ -0,0 +1,457 @@
+use nalgebra_glm::Vec3;
+use std::f32::consts::PI;
+use crate::bvh::{Aabb, Bounded};
+use crate::light::orthonormal_basis;
+use crate::material::Material;
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+const EPSILON: f32 = 1e-6;
+const PLANE_EXTENT: f32 = 1e5; // Half size of the box that stands in for an infinite plane
+
+/// Ball around `center`; rays starting inside hit its far side, so it can be glass.
+#[derive(Debug, Clone)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: f32, material: Material) -> Self {
+        Sphere { center, radius, material }
+    }
+
+    /// Distance a ray starting inside the sphere travels before leaving it, or None from outside.
+    pub fn exit_distance(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+        if (ray_origin - self.center).magnitude_squared() > self.radius * self.radius {
+            return None;
+        }
+        self.roots(ray_origin, ray_direction).map(|(_, far)| far.max(0.0))
+    }
+
+    /// Distances where the ray crosses the surface, nearest first.
+    fn roots(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<(f32, f32)> {
+        let oc = ray_origin - self.center;
+        let a = ray_direction.dot(ray_direction);
+        let half_b = oc.dot(ray_direction);
+        let c = oc.dot(&oc) - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        Some(((-half_b - root) / a, (-half_b + root) / a))
+    }
+}
+
+impl RayIntersect for Sphere {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let (near, far) = match self.roots(ray_origin, ray_direction) {
+            Some(roots) => roots,
+            None => return Intersect::empty(),
+        };
+
+        for t in [near, far] {
+            if t <= EPSILON {
+                continue;
+            }
+            let point = ray_origin + ray_direction * t;
+            let outward = (point - self.center) / self.radius;
+            // Longitude around +Y and latitude from the south pole
+            let u = 0.5 + outward.z.atan2(outward.x) / (2.0 * PI);
+            let v = 1.0 - outward.y.clamp(-1.0, 1.0).acos() / PI;
+            if !self.material.is_solid_at(u, v) {
+                continue;
+            }
+            // Rays from inside keep the outward normal for refraction; the far side seen
+            // through a cutout hole faces the viewer like the inside of a cube
+            let normal = if t == far && near > EPSILON { -outward } else { outward };
+            let mut intersect = Intersect::new(point, normal, t, self.material.clone(), (u, v));
+            intersect.uv_size = 2.0 * PI * self.radius;
+            return intersect;
+        }
+        Intersect::empty()
+    }
+}
+
+impl Bounded for Sphere {
+    fn bounds(&self) -> Aabb {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+/// Infinite two-sided plane through `point`; its texture repeats every world unit.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Material) -> Self {
+        Plane { point, normal: normal.normalize(), material }
+    }
+}
+
+impl RayIntersect for Plane {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let t = match hit_plane(&self.point, &self.normal, ray_origin, ray_direction) {
+            Some(t) => t,
+            None => return Intersect::empty(),
+        };
+        let point = ray_origin + ray_direction * t;
+        let (tangent, bitangent) = orthonormal_basis(&self.normal);
+        let local = point - self.point;
+        let uv = (local.dot(&tangent), local.dot(&bitangent));
+        surface_hit(&self.material, point, facing(&self.normal, ray_direction), t, uv, 1.0)
+    }
+}
+
+impl Bounded for Plane {
+    /// A very large box, flat along the normal when the plane is axis aligned.
+    fn bounds(&self) -> Aabb {
+        let mut min = self.point - Vec3::new(PLANE_EXTENT, PLANE_EXTENT, PLANE_EXTENT);
+        let mut max = self.point + Vec3::new(PLANE_EXTENT, PLANE_EXTENT, PLANE_EXTENT);
+        for axis in 0..3 {
+            if (self.normal[axis].abs() - 1.0).abs() < EPSILON {
+                min[axis] = self.point[axis];
+                max[axis] = self.point[axis];
+            }
+        }
+        Aabb::new(min, max)
+    }
+}
+
+/// Two-sided parallelogram spanned by the edges `u` and `v` from `corner`.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3, material: Material) -> Self {
+        Quad { corner, u, v, material }
+    }
+}
+
+impl RayIntersect for Quad {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let n = self.u.cross(&self.v);
+        let normal = n.normalize();
+        let t = match hit_plane(&self.corner, &normal, ray_origin, ray_direction) {
+            Some(t) => t,
+            None => return Intersect::empty(),
+        };
+
+        // Coordinates of the hit along the edges, from 0 to 1 inside the quad
+        let point = ray_origin + ray_direction * t;
+        let local = point - self.corner;
+        let w = n / n.dot(&n);
+        let a = w.dot(&local.cross(&self.v));
+        let b = w.dot(&self.u.cross(&local));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return Intersect::empty();
+        }
+        let uv_size = self.u.magnitude().max(self.v.magnitude());
+        surface_hit(&self.material, point, facing(&normal, ray_direction), t, (a, b), uv_size)
+    }
+}
+
+impl Bounded for Quad {
+    fn bounds(&self) -> Aabb {
+        let corners = [self.corner + self.u, self.corner + self.v, self.corner + self.u + self.v];
+        corners.iter().fold(Aabb::new(self.corner, self.corner), |acc, corner| acc.grow(corner))
+    }
+}
+
+/// Two-sided flat disc; its texture is stretched over the square around it.
+#[derive(Debug, Clone)]
+pub struct Disc {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Disc {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Material) -> Self {
+        Disc { center, normal: normal.normalize(), radius, material }
+    }
+}
+
+impl RayIntersect for Disc {
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let t = match hit_plane(&self.center, &self.normal, ray_origin, ray_direction) {
+            Some(t) => t,
+            None => return Intersect::empty(),
+        };
+        let point = ray_origin + ray_direction * t;
+        let local = point - self.center;
+        if local.magnitude_squared() > self.radius * self.radius {
+            return Intersect::empty();
+        }
+        let (tangent, bitangent) = orthonormal_basis(&self.normal);
+        let diameter = 2.0 * self.radius;
+        let uv = (local.dot(&tangent) / diameter + 0.5, local.dot(&bitangent) / diameter + 0.5);
+        surface_hit(&self.material, point, facing(&self.normal, ray_direction), t, uv, diameter)
+    }
+}
+
+impl Bounded for Disc {
+    fn bounds(&self) -> Aabb {
+        // Extent of the rim along each axis
+        let n = self.normal;
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+/// Triangle with optional per-vertex normals and texture coordinates.
+///
+/// The normal follows the counter-clockwise winding and is not flipped towards the ray, so
+/// closed meshes know which side is inside when they refract.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub normals: Option<[Vec3; 3]>, // Smooth shading when set
+    pub uvs: [(f32, f32); 3],
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, material: Material) -> Self {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    /// World-space length spanned by one unit of UV, from the areas of both triangles.
+    fn uv_size(&self) -> f32 {
+        let [a, b, c] = self.vertices;
+        let area = (b - a).cross(&(c - a)).magnitude();
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs;
+        let uv_area = ((u1 - u0) * (v2 - v0) - (u2 - u0) * (v1 - v0)).abs();
+        if uv_area > EPSILON {
+            (area / uv_area).sqrt()
+        } else {
+            (b - a).magnitude().max((c - a).magnitude())
+        }
+    }
+}
+
+impl RayIntersect for Triangle {
+    /// Möller–Trumbore intersection.
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let [a, b, c] = self.vertices;
+        let (edge1, edge2) = (b - a, c - a);
+        let p = ray_direction.cross(&edge2);
+        let determinant = edge1.dot(&p);
+        if determinant.abs() < EPSILON {
+            return Intersect::empty(); // Parallel to the triangle
+        }
+
+        let inv_determinant = 1.0 / determinant;
+        let s = ray_origin - a;
+        let beta = s.dot(&p) * inv_determinant;
+        if !(0.0..=1.0).contains(&beta) {
+            return Intersect::empty();
+        }
+        let q = s.cross(&edge1);
+        let gamma = ray_direction.dot(&q) * inv_determinant;
+        if gamma < 0.0 || beta + gamma > 1.0 {
+            return Intersect::empty();
+        }
+        let t = edge2.dot(&q) * inv_determinant;
+        if t <= EPSILON {
+            return Intersect::empty();
+        }
+
+        let alpha = 1.0 - beta - gamma;
+        let normal = match self.normals {
+            Some([na, nb, nc]) => (na * alpha + nb * beta + nc * gamma).normalize(),
+            None => edge1.cross(&edge2).normalize(),
+        };
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs;
+        let uv = (u0 * alpha + u1 * beta + u2 * gamma, v0 * alpha + v1 * beta + v2 * gamma);
+        let point = ray_origin + ray_direction * t;
+        surface_hit(&self.material, point, normal, t, uv, self.uv_size())
+    }
+}
+
+impl Bounded for Triangle {
+    fn bounds(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        Aabb::new(a, a).grow(&b).grow(&c)
+    }
+}
+
+/// Distance along the ray to the plane, if it lies ahead and the ray is not parallel to it.
+fn hit_plane(point: &Vec3, normal: &Vec3, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+    let denominator = normal.dot(ray_direction);
+    if denominator.abs() < EPSILON {
+        return None;
+    }
+    let t = (point - ray_origin).dot(normal) / denominator;
+    if t > EPSILON { Some(t) } else { None }
+}
+
+/// Normal of a two-sided surface, turned towards the incoming ray.
+fn facing(normal: &Vec3, ray_direction: &Vec3) -> Vec3 {
+    if normal.dot(ray_direction) > 0.0 { -normal } else { *normal }
+}
+
+/// Builds the hit, unless an alpha cutout leaves a hole at `uv`.
+fn surface_hit(material: &Material, point: Vec3, normal: Vec3, t: f32, uv: (f32, f32), uv_size: f32) -> Intersect {
+    if !material.is_solid_at(uv.0, uv.1) {
+        return Intersect::empty();
+    }
+    let mut intersect = Intersect::new(point, normal, t, material.clone(), uv);
+    intersect.uv_size = uv_size;
+    intersect
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn material() -> Material {
+        Material::new(Color::new(200, 200, 200), 10.0, [0.9, 0.0], 0.0, 0.0, 1.0)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} vs {:?}", a, b);
+    }
+
+    fn assert_hit(intersect: &Intersect, distance: f32, normal: Vec3, uv: (f32, f32)) {
+        assert!(intersect.is_intersecting);
+        assert!((intersect.distance - distance).abs() < 1e-5, "distance {}", intersect.distance);
+        assert_close(intersect.normal, normal);
+        assert!((intersect.uv.0 - uv.0).abs() < 1e-5 && (intersect.uv.1 - uv.1).abs() < 1e-5, "uv {:?}", intersect.uv);
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, material());
+        let down_z = Vec3::new(0.0, 0.0, -1.0);
+
+        // +Z is a quarter turn around +Y from +X, on the equator
+        let hit = sphere.ray_intersect(&Vec3::new(0.0, 0.0, 5.0), &down_z);
+        assert_hit(&hit, 4.0, Vec3::new(0.0, 0.0, 1.0), (0.75, 0.5));
+        assert_close(hit.point, Vec3::new(0.0, 0.0, 1.0));
+        assert!((hit.uv_size - 2.0 * PI).abs() < 1e-5);
+
+        // The north pole sits at the top of the texture
+        let hit = sphere.ray_intersect(&Vec3::new(0.0, 3.0, 0.0), &Vec3::new(0.0, -1.0, 0.0));
+        assert!(hit.is_intersecting && (hit.uv.1 - 1.0).abs() < 1e-5);
+
+        assert!(!sphere.ray_intersect(&Vec3::new(1.5, 0.0, 5.0), &down_z).is_intersecting);
+        assert!(!sphere.ray_intersect(&Vec3::new(0.0, 0.0, 5.0), &-down_z).is_intersecting); // Behind the ray
+
+        // From inside, the far side is hit and the normal still points out, for refraction
+        let hit = sphere.ray_intersect(&Vec3::new(0.0, 0.0, 0.5), &Vec3::new(1.0, 0.0, 0.0));
+        assert!(hit.is_intersecting);
+        assert!((hit.distance - 0.75f32.sqrt()).abs() < 1e-5);
+        assert!((hit.normal.magnitude() - 1.0).abs() < 1e-5 && hit.normal.x > 0.0);
+        assert!((sphere.exit_distance(&Vec3::zeros(), &down_z).unwrap() - 1.0).abs() < 1e-5);
+        assert_eq!(sphere.exit_distance(&Vec3::new(0.0, 0.0, 5.0), &down_z), None);
+    }
+
+    #[test]
+    fn plane() {
+        let plane = Plane::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), material());
+        let (tangent, bitangent) = orthonormal_basis(&Vec3::new(0.0, 1.0, 0.0));
+        let point = Vec3::new(2.25, 1.0, -3.5);
+        let local = point - plane.point;
+        let uv = (local.dot(&tangent), local.dot(&bitangent));
+
+        // Two-sided: the normal faces whichever side the ray comes from
+        let hit = plane.ray_intersect(&(point + Vec3::new(0.0, 3.0, 0.0)), &Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(&hit, 3.0, Vec3::new(0.0, 1.0, 0.0), uv);
+        let hit = plane.ray_intersect(&(point - Vec3::new(0.0, 2.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0));
+        assert_hit(&hit, 2.0, Vec3::new(0.0, -1.0, 0.0), uv);
+
+        assert!(!plane.ray_intersect(&Vec3::new(0.0, 3.0, 0.0), &Vec3::new(1.0, 0.0, 0.0)).is_intersecting); // Parallel
+        assert!(!plane.ray_intersect(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(0.0, 0.0, 1.0)).is_intersecting); // Within it
+        assert!(!plane.ray_intersect(&Vec3::new(0.0, 3.0, 0.0), &Vec3::new(0.0, 1.0, 0.0)).is_intersecting); // Moving away
+    }
+
+    #[test]
+    fn quad() {
+        let quad = Quad::new(Vec3::zeros(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), material());
+        let down_z = Vec3::new(0.0, 0.0, -1.0);
+
+        let hit = quad.ray_intersect(&Vec3::new(1.5, 0.25, 3.0), &down_z);
+        assert_hit(&hit, 3.0, Vec3::new(0.0, 0.0, 1.0), (0.75, 0.25));
+        assert_eq!(hit.uv_size, 2.0);
+        let hit = quad.ray_intersect(&Vec3::new(0.5, 0.5, -1.0), &-down_z);
+        assert_hit(&hit, 1.0, Vec3::new(0.0, 0.0, -1.0), (0.25, 0.5));
+
+        assert!(!quad.ray_intersect(&Vec3::new(2.5, 0.25, 3.0), &down_z).is_intersecting);
+        assert!(!quad.ray_intersect(&Vec3::new(1.0, -0.1, 3.0), &down_z).is_intersecting);
+        assert!(!quad.ray_intersect(&Vec3::new(-1.0, 0.5, 0.0), &Vec3::new(1.0, 0.0, 0.0)).is_intersecting); // Parallel
+    }
+
+    #[test]
+    fn disc() {
+        let disc = Disc::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.5, material());
+        let (tangent, bitangent) = orthonormal_basis(&disc.normal);
+        let offset = Vec3::new(0.2, 0.0, -0.3);
+        let uv = (offset.dot(&tangent) + 0.5, offset.dot(&bitangent) + 0.5); // Divided by the diameter of 1
+
+        let hit = disc.ray_intersect(&(disc.center + offset + Vec3::new(0.0, 2.0, 0.0)), &Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(&hit, 2.0, Vec3::new(0.0, 1.0, 0.0), uv);
+        let hit = disc.ray_intersect(&(disc.center + offset - Vec3::new(0.0, 1.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0));
+        assert_hit(&hit, 1.0, Vec3::new(0.0, -1.0, 0.0), uv);
+
+        // Inside the bounding square but outside the rim
+        assert!(!disc.ray_intersect(&Vec3::new(0.4, 3.0, 0.4), &Vec3::new(0.0, -1.0, 0.0)).is_intersecting);
+        assert!(!disc.ray_intersect(&Vec3::new(-2.0, 1.0, 0.0), &Vec3::new(1.0, 0.0, 0.0)).is_intersecting); // Parallel
+    }
+
+    #[test]
+    fn triangle() {
+        let triangle = Triangle::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), material());
+        let down_z = Vec3::new(0.0, 0.0, -1.0);
+
+        // Barycentric UVs, and the winding normal from either side
+        let hit = triangle.ray_intersect(&Vec3::new(0.25, 0.5, 2.0), &down_z);
+        assert_hit(&hit, 2.0, Vec3::new(0.0, 0.0, 1.0), (0.25, 0.5));
+        let hit = triangle.ray_intersect(&Vec3::new(0.25, 0.5, -1.0), &-down_z);
+        assert_hit(&hit, 1.0, Vec3::new(0.0, 0.0, 1.0), (0.25, 0.5));
+
+        assert!(!triangle.ray_intersect(&Vec3::new(0.75, 0.75, 2.0), &down_z).is_intersecting);
+        assert!(!triangle.ray_intersect(&Vec3::new(-0.5, 0.25, 0.0), &Vec3::new(1.0, 0.0, 0.0)).is_intersecting); // Parallel
+
+        // Smooth normals and custom UVs are interpolated
+        let tilted = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let smooth = triangle
+            .with_normals([Vec3::new(0.0, 0.0, 1.0), tilted, Vec3::new(0.0, 0.0, 1.0)])
+            .with_uvs([(0.0, 0.0), (2.0, 0.0), (0.0, 4.0)]);
+        let hit = smooth.ray_intersect(&Vec3::new(0.5, 0.25, 2.0), &down_z);
+        let expected_normal = (Vec3::new(0.0, 0.0, 1.0) * 0.5 + tilted * 0.5).normalize();
+        assert_hit(&hit, 2.0, expected_normal, (1.0, 1.0));
+    }
+}
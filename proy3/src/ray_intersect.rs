@@ -12,6 +12,7 @@ pub struct Intersect {
     pub is_intersecting: bool, // Flag indicating if an intersection occurred
     pub material: Material,    // Material of the intersected object
     pub uv: (f32, f32),       // Texture coordinates
+    pub uv_size: f32,         // World-space length spanned by one unit of UV
 }
 
 impl Intersect {
@@ -24,6 +25,7 @@ impl Intersect {
             is_intersecting: true,
             material,
             uv,
+            uv_size: 1.0,
         }
     }
 
@@ -43,6 +45,7 @@ impl Intersect {
                 0.0,
             ),
             uv: (0.0, 0.0),
+            uv_size: 1.0,
         }
     }
 }
@@ -1,48 +1,159 @@
 use crate::Framebuffer;
-use crate::Cube;
+use crate::scene::Scene;
 use crate::Camera;
 use crate::Light;
 use std::{f32::consts::PI};
 use rayon::prelude::*;
-use crate::cast_ray;
+use crate::castray::{cast_ray, RayCone};
+use crate::pathtrace::trace_path;
 use nalgebra_glm::Vec3;
-use std::sync::Arc;
-use crate::Texture;
+use crate::texture_registry::TextureRegistry;
+use crate::antialias::{contrast, AntiAliasing};
+use crate::color::HdrColor;
+use crate::environment::Environment;
+use crate::sky::Sky;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-/// Renders the scene to the framebuffer.
+/// Renders the scene into the framebuffer's radiance buffer.
+///
+/// Call `Framebuffer::resolve` afterwards to get displayable pixels.
 pub fn render(
     framebuffer: &mut Framebuffer, 
-    objects: &[Cube], 
+    scene: &Scene,
     camera: &Camera, 
     lights: &[Light], 
     daylight: &Light, 
-    textures: &[Arc<Texture>]  // Added textures argument
+    textures: &TextureRegistry,
+    antialiasing: &AntiAliasing
 ) {
     let width = framebuffer.width as f32;
     let height = framebuffer.height as f32;
-    let aspect_ratio = width / height;
-    let fov = PI / 3.0; // Field of view
-    let perspective_scale = (fov / 2.0).tan(); // Perspective scale based on FOV
+    let cone = RayCone::pixel(2.0 * perspective_scale() / height); // Angle covered by one pixel
+    let environment = scene_environment(scene, daylight);
+
+    // Traces one ray through the (fractional) pixel position
+    let trace = |x: f32, y: f32| -> HdrColor {
+        let direction = primary_ray(camera, x, y, width, height);
+        cast_ray(&camera.eye, &direction, scene, daylight, lights, textures, &environment, cone, 0)
+    };
 
-    framebuffer.buffer.par_chunks_mut(framebuffer.width as usize).enumerate().for_each(|(y, row)| {
-        let screen_y = -(2.0 * y as f32) / height + 1.0; // Transform to normalized device coordinates
-        let screen_y = screen_y * perspective_scale; // Scale for perspective
+    // Filtered average of N×N samples around the pixel
+    let supersample = |x: f32, y: f32, rng: &mut StdRng| -> HdrColor {
+        let mut sum = HdrColor::black();
+        let mut total_weight = 0.0;
+        for (dx, dy) in antialiasing.offsets(rng) {
+            let weight = antialiasing.filter.weight(dx, dy);
+            if weight > 0.0 {
+                sum += trace(x + dx, y + dy) * weight;
+                total_weight += weight;
+            }
+        }
+        if total_weight > 0.0 { sum * (1.0 / total_weight) } else { trace(x, y) }
+    };
 
+    let row_width = framebuffer.width;
+    let supersample_all = antialiasing.is_enabled() && antialiasing.adaptive.is_none();
+
+    framebuffer.radiance.par_chunks_mut(row_width).enumerate().for_each(|(y, row)| {
+        let mut rng = StdRng::seed_from_u64(y as u64); // Same samples every frame, so the image doesn't shimmer
         row.iter_mut().enumerate().for_each(|(x, pixel)| {
-            let screen_x = (2.0 * x as f32) / width - 1.0; // Transform to normalized device coordinates
-            let screen_x = screen_x * aspect_ratio * perspective_scale; // Scale for aspect ratio
+            *pixel = if supersample_all {
+                supersample(x as f32, y as f32, &mut rng)
+            } else {
+                trace(x as f32, y as f32)
+            }; // Store the linear color in the framebuffer
+        });
+    });
 
-            // Construct the ray direction in camera space
-            let ray_direction = Vec3::new(screen_x, screen_y, -1.0).normalize();
+    // Adaptive mode: refine only the pixels that differ from a neighbour
+    if let (true, Some(threshold)) = (antialiasing.is_enabled(), antialiasing.adaptive) {
+        let rows = framebuffer.height;
+        let luminance: Vec<f32> = framebuffer.radiance.iter().map(HdrColor::luminance).collect();
+        let is_edge = |x: usize, y: usize| {
+            let center = luminance[y * row_width + x];
+            let neighbours = [
+                (x > 0).then(|| luminance[y * row_width + x - 1]),
+                (x + 1 < row_width).then(|| luminance[y * row_width + x + 1]),
+                (y > 0).then(|| luminance[(y - 1) * row_width + x]),
+                (y + 1 < rows).then(|| luminance[(y + 1) * row_width + x]),
+            ];
+            neighbours.into_iter().flatten().any(|n| contrast(center, n) > threshold)
+        };
 
-            // Rotate the ray direction based on the camera's orientation
-            let rotated_direction = camera.basis_change(&ray_direction);
+        framebuffer.radiance.par_chunks_mut(row_width).enumerate().for_each(|(y, row)| {
+            let mut rng = StdRng::seed_from_u64(y as u64);
+            for (x, pixel) in row.iter_mut().enumerate() {
+                if is_edge(x, y) {
+                    *pixel = supersample(x as f32, y as f32, &mut rng);
+                }
+            }
+        });
+    }
+}
+
+/// Adds one path traced sample per pixel to the accumulation buffer and writes the running
+/// average into the radiance buffer.
+///
+/// Call `Framebuffer::reset_accumulation` whenever the camera or a light changes.
+pub fn render_progressive(
+    framebuffer: &mut Framebuffer,
+    scene: &Scene,
+    camera: &Camera,
+    lights: &[Light],
+    daylight: &Light,
+    textures: &TextureRegistry
+) {
+    let width = framebuffer.width as f32;
+    let height = framebuffer.height as f32;
+    let cone = RayCone::pixel(2.0 * perspective_scale() / height);
+    let environment = scene_environment(scene, daylight);
+    let pass = framebuffer.samples as u64;
+    let scale = 1.0 / (framebuffer.samples + 1) as f32;
 
-            // Cast the ray from the camera's position in the direction of the rotated ray
-            let pixel_color = cast_ray(&camera.eye, &rotated_direction, objects, daylight, lights, textures, 0);  // Pass textures here
-            *pixel = pixel_color.to_u32(); // Store the color in the framebuffer
+    framebuffer
+        .accumulation
+        .par_chunks_mut(framebuffer.width)
+        .zip(framebuffer.radiance.par_chunks_mut(framebuffer.width))
+        .enumerate()
+        .for_each(|(y, (sums, row))| {
+            let mut rng = StdRng::seed_from_u64((pass << 32) | y as u64); // New random numbers every pass
+            for (x, (sum, pixel)) in sums.iter_mut().zip(row.iter_mut()).enumerate() {
+                // Random position inside the pixel, which also anti-aliases the image
+                let sample_x = x as f32 + rng.gen::<f32>() - 0.5;
+                let sample_y = y as f32 + rng.gen::<f32>() - 0.5;
+                let direction = primary_ray(camera, sample_x, sample_y, width, height);
+                *sum += trace_path(&camera.eye, &direction, scene, daylight, lights, textures, &environment, cone, &mut rng);
+                *pixel = *sum * scale;
+            }
         });
-    });
+
+    framebuffer.samples += 1;
 }
 
+/// The scene's environment map, or the sky lit by `daylight` when it has none.
+fn scene_environment<'a>(scene: &'a Scene, daylight: &Light) -> Environment<'a> {
+    match &scene.environment {
+        Some(map) => Environment::Map(map),
+        None => Environment::Sky(Sky::from_light(daylight)),
+    }
+}
 
+fn perspective_scale() -> f32 {
+    let fov = PI / 3.0; // Field of view
+    (fov / 2.0).tan()
+}
+
+/// World-space direction of the camera ray through pixel (`x`, `y`).
+fn primary_ray(camera: &Camera, x: f32, y: f32, width: f32, height: f32) -> Vec3 {
+    let aspect_ratio = width / height;
+    let perspective_scale = perspective_scale();
+
+    let screen_x = (2.0 * x) / width - 1.0; // Transform to normalized device coordinates
+    let screen_x = screen_x * aspect_ratio * perspective_scale; // Scale for aspect ratio
+    let screen_y = -(2.0 * y) / height + 1.0;
+    let screen_y = screen_y * perspective_scale; // Scale for perspective
+
+    // Construct the ray direction in camera space, then rotate it by the camera's orientation
+    let ray_direction = Vec3::new(screen_x, screen_y, -1.0).normalize();
+    camera.basis_change(&ray_direction)
+}
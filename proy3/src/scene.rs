@@ -0,0 +1,120 @@
+use nalgebra_glm::Vec3;
+use crate::bvh::Bvh;
+use crate::emitter::Emitters;
+use crate::environment::EnvironmentMap;
+use crate::object::Object;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::voxel::VoxelWorld;
+
+/// Geometry the tracer shoots rays against.
+pub struct Scene {
+    objects: Vec<Object>, // Only changed through `edit_objects`, which keeps `bvh` and `emitters` in sync
+    voxels: Vec<VoxelWorld>,
+    pub environment: Option<EnvironmentMap>, // Replaces the analytic sky when set
+    bvh: Bvh,
+    emitters: Emitters,
+}
+
+impl Scene {
+    /// Creates a scene and builds the acceleration structure over `objects`.
+    pub fn new(objects: Vec<Object>, voxels: Vec<VoxelWorld>) -> Self {
+        let bvh = Bvh::build(&objects);
+        let emitters = Emitters::collect(&objects, &voxels);
+        Scene { objects, voxels, environment: None, bvh, emitters }
+    }
+
+    /// Lights the scene with a captured environment instead of the sky.
+    pub fn with_environment(mut self, environment: EnvironmentMap) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Objects of the scene, in the order they were given.
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Voxel grids of the scene.
+    pub fn voxels(&self) -> &[VoxelWorld] {
+        &self.voxels
+    }
+
+    /// Glowing surfaces the path tracer samples as lights.
+    pub fn emitters(&self) -> &Emitters {
+        &self.emitters
+    }
+
+    /// Lets `edit` move, add or remove objects, then brings the BVH up to date (a refit when
+    /// the count is unchanged, a rebuild otherwise) and gathers the glowing surfaces again.
+    pub fn edit_objects(&mut self, edit: impl FnOnce(&mut Vec<Object>)) {
+        edit(&mut self.objects);
+        self.bvh.refit(&self.objects);
+        self.emitters = Emitters::collect(&self.objects, &self.voxels);
+    }
+
+    /// Returns the closest intersection with any object or voxel.
+    pub fn closest_hit(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let mut intersect = self.bvh.closest_hit(&self.objects, ray_origin, ray_direction);
+
+        for world in &self.voxels {
+            let i = world.ray_intersect(ray_origin, ray_direction);
+            if i.is_intersecting && (!intersect.is_intersecting || i.distance < intersect.distance) {
+                intersect = i;
+            }
+        }
+
+        intersect
+    }
+
+    /// Distance a ray starting inside a solid object travels before leaving it.
+    ///
+    /// Only objects whose bounds contain the origin are asked, found through the BVH.
+    pub fn distance_inside(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+        let mut distance = self.voxels.iter().filter_map(|world| world.exit_distance(ray_origin, ray_direction)).reduce(f32::max);
+        self.bvh.for_each_containing(&self.objects, ray_origin, |object| {
+            if let Some(exit) = object.exit_distance(ray_origin, ray_direction) {
+                distance = Some(distance.map_or(exit, |d| d.max(exit)));
+            }
+        });
+        distance
+    }
+
+    /// Returns true if anything blocks the ray before `max_distance`.
+    pub fn any_hit(&self, ray_origin: &Vec3, ray_direction: &Vec3, max_distance: f32) -> bool {
+        if self.bvh.any_hit(&self.objects, ray_origin, ray_direction, max_distance) {
+            return true;
+        }
+
+        self.voxels.iter().any(|world| {
+            let i = world.ray_intersect(ray_origin, ray_direction);
+            i.is_intersecting && i.distance < max_distance
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::object::Cube;
+
+    #[test]
+    fn edited_objects_are_hit_where_they_are() {
+        let material = Material::new(Color::new(255, 255, 255), 10.0, [1.0, 0.0], 0.0, 0.0, 1.0);
+        let cube = |x: f32| Cube::new(Vec3::new(x, 0.0, 0.0), Vec3::new(x + 1.0, 1.0, 1.0), material.clone()).into();
+        let mut scene = Scene::new(vec![cube(0.0), cube(2.0)], Vec::new());
+        let origin = Vec3::new(5.5, 0.5, 5.0);
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        assert!(!scene.closest_hit(&origin, &direction).is_intersecting);
+
+        // Moved: refit
+        scene.edit_objects(|objects| objects[1] = cube(5.0));
+        assert!(scene.closest_hit(&origin, &direction).is_intersecting);
+
+        // Added: rebuilt
+        scene.edit_objects(|objects| objects.push(cube(8.0)));
+        assert!(scene.any_hit(&Vec3::new(8.5, 0.5, 5.0), &direction, 10.0));
+        assert_eq!(scene.objects().len(), 3);
+    }
+}
@@ -0,0 +1,862 @@
+//! Loader for TOML scene descriptions.
+//!
+//! A scene file has the following sections; see `scenes/diorama.toml` for a full example.
+//!
+//! ```toml
+//! [camera]                    # eye, center and optional up (defaults to +Y)
+//! eye = [2.0, 2.0, 7.0]
+//! center = [1.5, 1.0, 0.0]
+//!
+//! [textures]                  # name = image path, relative to the scene file
+//! ice = "../src/imagenes/ice.webp"
+//!
+//! [materials.ice]             # every `Material` field; `texture` names an entry above
+//! texture = "ice"             # or is an image path; without it `diffuse = [r, g, b]` is used
+//! spec = 50.0
+//! albedo = [0.9, 0.1]
+//! reflectivity = 0.3          # optional, defaults to 0
+//! transparency = 0.2          # optional, defaults to 0
+//! refraction_index = 1.31     # optional, defaults to 1
+//! filter = "trilinear"        # optional: nearest, bilinear or trilinear (default)
+//! wrap = "repeat"             # optional: repeat (default), clamp or mirror
+//! fresnel = "exact"           # optional: none (default, fixed weights), schlick or exact
+//! absorption = [1.2, 0.4, 0.1] # optional Beer–Lambert coefficients per unit of distance (r, g, b)
+//! emission = [255, 120, 20]   # optional glow color; `emission_texture` (name or path) modulates it
+//! emission_strength = 2.0     # optional, defaults to 1
+//! alpha_cutoff = 0.5          # optional: texels of `texture` with less alpha are holes
+//!
+//! [blocks.furnace]            # optional per-face materials; `material` fills faces not listed
+//! material = "furnace_side"   # `sides` covers left/right/back/front, individual faces win
+//! top = "furnace_top"         # faces: left (-X), right (+X), bottom, top, back (-Z), front (+Z)
+//! left = "furnace_front"
+//!
+//! [daylight]                  # main light, dimmed and brightened by the viewer; or use [sun]
+//! position = [4.0, 5.0, 7.0]
+//! color = [229, 156, 19]
+//! intensity = 0.2
+//!
+//! shape = { type = "sphere", radius = 1.5 }  # optional, see below; point light without it
+//! samples = 16                # shadow rays per point for area shapes, defaults to 16
+//!
+//! [sun]                       # directional sun and moon following a clock, instead of [daylight]
+//! time = 16.5                 # hours since midnight; sunrise at 6, sunset at 18
+//! intensity = 1.0             # optional: noon intensity (1), moon_intensity (0.08),
+//! tilt = 0.5                  # path tilt in radians (0.5), angular_radius (0.05), samples (8)
+//!
+//! [environment]               # optional equirectangular .hdr/.exr image replacing the sky;
+//! path = "sky.hdr"            # relative to the scene file. With it [daylight]/[sun] are optional
+//! intensity = 1.0             # optional, defaults to 1
+//! rotation = 90.0             # optional, degrees around +Y
+//!
+//! [[lights]]                  # any number of additional lights
+//! position = [1.0, -0.95, 2.7]
+//! color = [220, 91, 2]
+//! intensity = 0.5
+//! # shapes: { type = "point" }, { type = "sphere", radius = r },
+//! #         { type = "disk", radius = r, normal = [x, y, z] } (normal defaults to +Y),
+//! #         { type = "rectangle", u = [x, y, z], v = [x, y, z] } (edge vectors around `position`)
+//!
+//! [[cubes]]                   # free-standing boxes; `material` may also name a block
+//! min = [1.5, -1.0, 2.5]
+//! max = [2.0, -0.5, 3.0]
+//! material = "ice"
+//!
+//! [[spheres]]                 # other shapes take a plain material, not a block
+//! center = [0.5, 0.0, 3.0]
+//! radius = 0.4
+//! material = "ice"
+//!
+//! # [[planes]]    point, normal (defaults to +Y); infinite, texture repeats every unit
+//! # [[quads]]     corner, u, v: parallelogram with edges `u` and `v` from `corner`
+//! # [[discs]]     center, normal (defaults to +Y), radius
+//! # [[triangles]] vertices = [[x, y, z], [x, y, z], [x, y, z]], optional uvs = [[u, v], ...]
+//!
+//! [[meshes]]                  # Wavefront OBJ model, with the materials of its MTL files
+//! path = "models/lantern.obj" # relative to the scene file; MTL textures to the OBJ
+//! position = [1.0, -0.5, 2.0] # optional placement: position, rotation (degrees around X,
+//! rotation = [0.0, 45.0, 0.0] # then Y, then Z) and uniform scale (1)
+//! scale = 0.5
+//! material = "ice"            # optional, replaces every MTL material
+//!
+//! [[voxels]]                  # block grids, see `VoxelWorld`
+//! origin = [0.0, -1.5, 0.0]
+//! voxel_size = 0.5
+//! size = [4, 6, 6]
+//!
+//! [[voxels.blocks]]           # fills the inclusive cell range `min..=max` (or only `min`)
+//! material = "ice"
+//! min = [0, 0, 3]
+//! max = [1, 0, 5]
+//!
+//! [[vox_models]]              # MagicaVoxel model, one grid per model with palette materials
+//! path = "models/house.vox"   # relative to the scene file
+//! position = [0.0, -1.0, 0.0] # optional, where the file's origin goes
+//! voxel_size = 0.1
+//!
+//! [[structures]]              # Minecraft build: Sponge .schem or structure block .nbt file
+//! path = "models/hut.schem"   # relative to the scene file
+//! mapping = "minecraft.toml"  # block states to scene materials, relative to the scene file
+//! position = [0.0, -1.0, 0.0] # optional, where the lowest corner goes
+//! voxel_size = 0.5
+//! ```
+//!
+//! A block mapping file names a material or block type of the scene for every block state;
+//! a state with properties wins over its bare block name, and air is always left empty:
+//!
+//! ```toml
+//! default = "stone"           # optional, for blocks not listed; without it they are an error
+//!
+//! [blocks]
+//! "minecraft:snow_block" = "snow"
+//! "minecraft:furnace" = "furnace"
+//! "minecraft:furnace[facing=south,lit=true]" = "furnace_lit"
+//! ```
+
+use nalgebra_glm::Vec3;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+use crate::camera::Camera;
+use crate::color::{Color, HdrColor};
+use crate::environment::EnvironmentMap;
+use crate::light::{Light, LightShape};
+use crate::material::{AlphaCutout, FresnelMode, Material};
+use crate::gltf_scene::load_gltf;
+use crate::mesh::placement;
+use crate::obj::load_obj;
+use crate::object::{Cube, Face, Object};
+use crate::primitives::{Disc, Plane, Quad, Sphere, Triangle};
+use crate::scene::Scene;
+use crate::schematic::{block_name, is_air, normalize_state, Structure};
+use crate::sun::DayCycle;
+use crate::texture::{FilterMode, Sampler, WrapMode};
+use crate::texture_registry::TextureRegistry;
+use crate::vox::load_vox;
+use crate::voxel::{BlockId, VoxelWorld};
+
+/// Everything the render loop needs, as described by a scene file.
+pub struct LoadedScene {
+    pub scene: Scene,
+    pub textures: TextureRegistry,
+    pub lights: Vec<Light>,
+    pub daylight: Light,          // The sun of `sun` when the scene has a day cycle
+    pub sun: Option<DayCycle>,
+    pub camera: Camera,
+}
+
+/// Error produced while reading a scene file.
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    pub line: Option<usize>, // 1-based line of the offending value, when known
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDef {
+    camera: CameraDef,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<String>>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDef>,
+    #[serde(default)]
+    blocks: BTreeMap<String, BlockTypeDef>,
+    daylight: Option<LightDef>,
+    sun: Option<SunDef>,
+    environment: Option<EnvironmentDef>,
+    #[serde(default)]
+    lights: Vec<LightDef>,
+    #[serde(default)]
+    cubes: Vec<CubeDef>,
+    #[serde(default)]
+    spheres: Vec<SphereDef>,
+    #[serde(default)]
+    planes: Vec<PlaneDef>,
+    #[serde(default)]
+    quads: Vec<QuadDef>,
+    #[serde(default)]
+    discs: Vec<DiscDef>,
+    #[serde(default)]
+    triangles: Vec<TriangleDef>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+    #[serde(default)]
+    voxels: Vec<VoxelDef>,
+    #[serde(default)]
+    vox_models: Vec<VoxModelDef>,
+    #[serde(default)]
+    structures: Vec<StructureDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    eye: [f32; 3],
+    center: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDef {
+    #[serde(default)]
+    diffuse: [i32; 3],
+    texture: Option<Spanned<String>>,
+    spec: f32,
+    albedo: [f32; 2],
+    #[serde(default)]
+    reflectivity: f32,
+    #[serde(default)]
+    transparency: f32,
+    #[serde(default = "default_refraction_index")]
+    refraction_index: f32,
+    #[serde(default)]
+    filter: FilterMode,
+    #[serde(default)]
+    wrap: WrapMode,
+    #[serde(default)]
+    fresnel: FresnelMode,
+    #[serde(default)]
+    absorption: [f32; 3],
+    emission: Option<[i32; 3]>,
+    #[serde(default = "default_intensity")]
+    emission_strength: f32,
+    emission_texture: Option<Spanned<String>>,
+    alpha_cutoff: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockTypeDef {
+    material: Option<Spanned<String>>,
+    sides: Option<Spanned<String>>,
+    top: Option<Spanned<String>>,
+    bottom: Option<Spanned<String>>,
+    left: Option<Spanned<String>>,
+    right: Option<Spanned<String>>,
+    back: Option<Spanned<String>>,
+    front: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDef {
+    position: [f32; 3],
+    color: [i32; 3],
+    intensity: f32,
+    shape: Option<ShapeDef>,
+    #[serde(default = "default_shadow_samples")]
+    samples: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDef {
+    path: Spanned<String>,
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+    #[serde(default)]
+    rotation: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SunDef {
+    time: f32,
+    intensity: Option<f32>,
+    moon_intensity: Option<f32>,
+    tilt: Option<f32>,
+    angular_radius: Option<f32>,
+    samples: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ShapeDef {
+    Point,
+    Rectangle { u: [f32; 3], v: [f32; 3] },
+    Disk {
+        #[serde(default = "default_up")]
+        normal: [f32; 3],
+        radius: f32,
+    },
+    Sphere { radius: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CubeDef {
+    min: [f32; 3],
+    max: [f32; 3],
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDef {
+    center: [f32; 3],
+    radius: f32,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneDef {
+    point: [f32; 3],
+    #[serde(default = "default_up")]
+    normal: [f32; 3],
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuadDef {
+    corner: [f32; 3],
+    u: [f32; 3],
+    v: [f32; 3],
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiscDef {
+    center: [f32; 3],
+    #[serde(default = "default_up")]
+    normal: [f32; 3],
+    radius: f32,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriangleDef {
+    vertices: [[f32; 3]; 3],
+    uvs: Option<[[f32; 2]; 3]>,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDef {
+    path: Spanned<String>,
+    #[serde(default)]
+    position: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: f32,
+    material: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VoxelDef {
+    origin: [f32; 3],
+    voxel_size: f32,
+    size: [usize; 3],
+    #[serde(default)]
+    blocks: Vec<BlockDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VoxModelDef {
+    path: Spanned<String>,
+    #[serde(default)]
+    position: [f32; 3],
+    voxel_size: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructureDef {
+    path: Spanned<String>,
+    mapping: Spanned<String>,
+    #[serde(default)]
+    position: [f32; 3],
+    voxel_size: f32,
+}
+
+/// Contents of a block mapping file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockMappingDef {
+    default: Option<Spanned<String>>,
+    #[serde(default)]
+    blocks: BTreeMap<String, Spanned<String>>, // Block state or name = material or block type
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDef {
+    material: Spanned<String>,
+    min: Spanned<[usize; 3]>,
+    max: Option<Spanned<[usize; 3]>>,
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_shadow_samples() -> u32 {
+    16
+}
+
+fn default_refraction_index() -> f32 {
+    1.0
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn color(c: [i32; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+fn light(def: &LightDef) -> Light {
+    let shape = match def.shape {
+        None | Some(ShapeDef::Point) => LightShape::Point,
+        Some(ShapeDef::Rectangle { u, v }) => LightShape::Rectangle { u: vec3(u), v: vec3(v) },
+        Some(ShapeDef::Disk { normal, radius }) => LightShape::Disk { normal: vec3(normal).normalize(), radius },
+        Some(ShapeDef::Sphere { radius }) => LightShape::Sphere { radius },
+    };
+    Light::new(vec3(def.position), color(def.color), def.intensity).with_shape(shape, def.samples)
+}
+
+/// Reads and validates the scene file at `path`, loading every texture it references.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<LoadedScene, SceneError> {
+    let path = path.as_ref();
+    if matches!(path.extension().and_then(|e| e.to_str()), Some("gltf" | "glb")) {
+        return load_gltf(path).map_err(|e| SceneError { path: e.path, line: None, message: e.message });
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| SceneError {
+        path: path.to_path_buf(),
+        line: None,
+        message: format!("cannot read scene file: {}", e),
+    })?;
+    parse_scene(&source, path)
+}
+
+/// Builds a scene from TOML source; relative texture paths are resolved against `path`'s directory.
+pub fn parse_scene(source: &str, path: &Path) -> Result<LoadedScene, SceneError> {
+    let error_at = |span: Option<Range<usize>>, message: String| SceneError {
+        path: path.to_path_buf(),
+        line: span.map(|span| source[..span.start.min(source.len())].matches('\n').count() + 1),
+        message,
+    };
+
+    let def: SceneDef = toml::from_str(source).map_err(|e| error_at(e.span(), e.message().to_string()))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut textures = TextureRegistry::new();
+    for (name, texture_path) in &def.textures {
+        let full_path = base_dir.join(texture_path.get_ref());
+        textures.load_named(name, &full_path).map_err(|e| {
+            error_at(
+                Some(texture_path.span()),
+                format!("cannot load texture '{}' from '{}': {}", name, full_path.display(), e),
+            )
+        })?;
+    }
+
+    let environment = match &def.environment {
+        Some(env) => {
+            let full_path = base_dir.join(env.path.get_ref());
+            let mut map = EnvironmentMap::load(&full_path).map_err(|e| {
+                error_at(
+                    Some(env.path.span()),
+                    format!("cannot load environment from '{}': {}", full_path.display(), e),
+                )
+            })?;
+            map.intensity = env.intensity;
+            map.set_rotation(env.rotation.to_radians());
+            Some(map)
+        }
+        None => None,
+    };
+
+    // Either a name from `[textures]` or an image path relative to the scene file
+    let resolve_texture = |textures: &mut TextureRegistry, texture: &Spanned<String>| match textures.find(texture.get_ref()) {
+        Some(handle) => Ok(handle),
+        None => textures.load(base_dir.join(texture.get_ref())).map_err(|e| {
+            error_at(
+                Some(texture.span()),
+                format!(
+                    "unknown texture '{}' (not a [textures] name nor a loadable image: {})",
+                    texture.get_ref(),
+                    e
+                ),
+            )
+        }),
+    };
+
+    let mut materials = HashMap::new();
+    for (name, m) in &def.materials {
+        let mut material = Material::new(
+            color(m.diffuse),
+            m.spec,
+            m.albedo,
+            m.reflectivity,
+            m.transparency,
+            m.refraction_index,
+        );
+        material.sampler = Sampler { filter: m.filter, wrap: m.wrap };
+        material.fresnel = m.fresnel;
+        material.absorption = HdrColor::new(m.absorption[0], m.absorption[1], m.absorption[2]);
+        if let Some(texture) = &m.texture {
+            material.texture = Some(resolve_texture(&mut textures, texture)?);
+        }
+        if let Some(cutoff) = &m.alpha_cutoff {
+            // The cutout follows the alpha channel of the diffuse texture
+            let handle = material.texture.ok_or_else(|| {
+                error_at(Some(cutoff.span()), format!("material '{}' needs a texture to use alpha_cutoff", name))
+            })?;
+            material.cutout = Some(AlphaCutout {
+                threshold: *cutoff.get_ref(),
+                mask: textures.get(handle).alpha_mask(),
+            });
+        }
+        if let Some(texture) = &m.emission_texture {
+            material.emission_texture = Some(resolve_texture(&mut textures, texture)?);
+        }
+        // A texture without an explicit color emits its own colors
+        let emission = match (m.emission, &m.emission_texture) {
+            (Some(emission), _) => color(emission).into(),
+            (None, Some(_)) => HdrColor::new(1.0, 1.0, 1.0),
+            (None, None) => HdrColor::black(),
+        };
+        material.emission = emission * m.emission_strength;
+        materials.insert(name.as_str(), material);
+    }
+
+    let find_material = |name: &Spanned<String>| {
+        materials
+            .get(name.get_ref().as_str())
+            .cloned()
+            .ok_or_else(|| error_at(Some(name.span()), format!("unknown material '{}'", name.get_ref())))
+    };
+
+    let mut block_types: HashMap<&str, [Option<Material>; 6]> = HashMap::new();
+    for (name, b) in &def.blocks {
+        let mut faces: [Option<Material>; 6] = Default::default();
+        let sides = [Face::Left, Face::Right, Face::Back, Face::Front];
+        let layers: [(&Option<Spanned<String>>, &[Face]); 8] = [
+            (&b.material, &Face::ALL),
+            (&b.sides, &sides),
+            (&b.top, &[Face::Top]),
+            (&b.bottom, &[Face::Bottom]),
+            (&b.left, &[Face::Left]),
+            (&b.right, &[Face::Right]),
+            (&b.back, &[Face::Back]),
+            (&b.front, &[Face::Front]),
+        ];
+        for (material_name, covered) in layers {
+            if let Some(material_name) = material_name {
+                let material = find_material(material_name)?;
+                for &face in covered {
+                    faces[face as usize] = Some(material.clone());
+                }
+            }
+        }
+        block_types.insert(name.as_str(), faces);
+    }
+
+    // A cube or voxel `material` may name either a block type or a plain material
+    let find_faces = |name: &Spanned<String>| -> Result<[Material; 6], SceneError> {
+        match block_types.get(name.get_ref().as_str()) {
+            Some(faces) => {
+                let mut resolved: [Material; 6] = std::array::from_fn(|_| Material::black());
+                for face in Face::ALL {
+                    resolved[face as usize] = faces[face as usize].clone().ok_or_else(|| {
+                        error_at(
+                            Some(name.span()),
+                            format!("block '{}' has no material for its {:?} face", name.get_ref(), face),
+                        )
+                    })?;
+                }
+                Ok(resolved)
+            }
+            None => find_material(name).map(|material| std::array::from_fn(|_| material.clone())),
+        }
+    };
+
+    let mut objects: Vec<Object> = Vec::new();
+    for cube in &def.cubes {
+        objects.push(Cube::with_faces(vec3(cube.min), vec3(cube.max), find_faces(&cube.material)?).into());
+    }
+    for sphere in &def.spheres {
+        objects.push(Sphere::new(vec3(sphere.center), sphere.radius, find_material(&sphere.material)?).into());
+    }
+    for plane in &def.planes {
+        objects.push(Plane::new(vec3(plane.point), vec3(plane.normal), find_material(&plane.material)?).into());
+    }
+    for quad in &def.quads {
+        objects.push(Quad::new(vec3(quad.corner), vec3(quad.u), vec3(quad.v), find_material(&quad.material)?).into());
+    }
+    for disc in &def.discs {
+        objects.push(Disc::new(vec3(disc.center), vec3(disc.normal), disc.radius, find_material(&disc.material)?).into());
+    }
+    for triangle in &def.triangles {
+        let [a, b, c] = triangle.vertices.map(vec3);
+        let mut shape = Triangle::new(a, b, c, find_material(&triangle.material)?);
+        if let Some(uvs) = triangle.uvs {
+            shape = shape.with_uvs(uvs.map(|[u, v]| (u, v)));
+        }
+        objects.push(shape.into());
+    }
+    for mesh in &def.meshes {
+        let material = mesh.material.as_ref().map(find_material).transpose()?;
+        let mut model = load_obj(base_dir.join(mesh.path.get_ref()), &mut textures, material.as_ref())
+            .map_err(|e| error_at(Some(mesh.path.span()), format!("cannot load mesh: {}", e)))?;
+        model.transform(&placement(vec3(mesh.position), vec3(mesh.rotation), mesh.scale));
+        objects.push(model.into());
+    }
+
+    let mut voxels = Vec::new();
+    for grid in &def.voxels {
+        let [size_x, size_y, size_z] = grid.size;
+        let mut world = VoxelWorld::new(size_x, size_y, size_z, vec3(grid.origin), grid.voxel_size);
+        let mut block_ids: HashMap<&str, BlockId> = HashMap::new();
+
+        for block in &grid.blocks {
+            let id = match block_ids.get(block.material.get_ref().as_str()) {
+                Some(&id) => id,
+                None => {
+                    let id = world
+                        .add_block_faces(find_faces(&block.material)?)
+                        .map_err(|e| error_at(Some(block.material.span()), e.to_string()))?;
+                    block_ids.insert(block.material.get_ref(), id);
+                    id
+                }
+            };
+
+            let min = *block.min.get_ref();
+            let (max, max_span) = match &block.max {
+                Some(max) => (*max.get_ref(), max.span()),
+                None => (min, block.min.span()),
+            };
+            for axis in 0..3 {
+                if max[axis] >= grid.size[axis] {
+                    return Err(error_at(Some(max_span), format!("block lies outside the {:?} voxel grid", grid.size)));
+                }
+                if min[axis] > max[axis] {
+                    return Err(error_at(Some(block.min.span()), "block min is greater than max".to_string()));
+                }
+            }
+
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        world.set(x, y, z, id);
+                    }
+                }
+            }
+        }
+        voxels.push(world);
+    }
+    for model in &def.vox_models {
+        let worlds = load_vox(base_dir.join(model.path.get_ref()), vec3(model.position), model.voxel_size)
+            .map_err(|e| error_at(Some(model.path.span()), format!("cannot load voxel model: {}", e)))?;
+        voxels.extend(worlds);
+    }
+    for structure_def in &def.structures {
+        let structure = Structure::load(base_dir.join(structure_def.path.get_ref()))
+            .map_err(|e| error_at(Some(structure_def.path.span()), format!("cannot load structure: {}", e)))?;
+
+        let mapping_path = base_dir.join(structure_def.mapping.get_ref());
+        let mapping_source = std::fs::read_to_string(&mapping_path).map_err(|e| {
+            error_at(
+                Some(structure_def.mapping.span()),
+                format!("cannot read block mapping '{}': {}", mapping_path.display(), e),
+            )
+        })?;
+        // Problems inside the mapping file are reported against it, not the scene
+        let mapping_error = |span: Option<Range<usize>>, message: String| SceneError {
+            path: mapping_path.clone(),
+            line: span.map(|span| mapping_source[..span.start.min(mapping_source.len())].matches('\n').count() + 1),
+            message,
+        };
+        let mapping: BlockMappingDef =
+            toml::from_str(&mapping_source).map_err(|e| mapping_error(e.span(), e.message().to_string()))?;
+        let states: HashMap<String, &Spanned<String>> =
+            mapping.blocks.iter().map(|(state, name)| (normalize_state(state), name)).collect();
+
+        let [size_x, size_y, size_z] = structure.size;
+        let mut world = VoxelWorld::new(size_x, size_y, size_z, vec3(structure_def.position), structure_def.voxel_size);
+        let mut block_ids: HashMap<&str, BlockId> = HashMap::new();
+        let mut unmapped = BTreeSet::new();
+        let mut palette_ids = Vec::with_capacity(structure.palette.len()); // None for empty cells
+        for state in &structure.palette {
+            if is_air(state) {
+                palette_ids.push(None);
+                continue;
+            }
+            let name = states
+                .get(state.as_str())
+                .or_else(|| states.get(block_name(state)))
+                .copied()
+                .or(mapping.default.as_ref());
+            let Some(name) = name else {
+                unmapped.insert(block_name(state));
+                palette_ids.push(None);
+                continue;
+            };
+            let id = match block_ids.get(name.get_ref().as_str()) {
+                Some(&id) => id,
+                None => {
+                    let faces = find_faces(name).map_err(|e| mapping_error(Some(name.span()), e.message))?;
+                    let id = world.add_block_faces(faces).map_err(|e| error_at(Some(structure_def.path.span()), e.to_string()))?;
+                    block_ids.insert(name.get_ref(), id);
+                    id
+                }
+            };
+            palette_ids.push(Some(id));
+        }
+        if !unmapped.is_empty() {
+            let names: Vec<&str> = unmapped.into_iter().collect();
+            return Err(mapping_error(
+                None,
+                format!("no material for {} (map them in [blocks] or set a default)", names.join(", ")),
+            ));
+        }
+
+        for x in 0..size_x {
+            for y in 0..size_y {
+                for z in 0..size_z {
+                    if let Some(id) = palette_ids[structure.palette_index(x, y, z)] {
+                        world.set(x, y, z, id);
+                    }
+                }
+            }
+        }
+        voxels.push(world);
+    }
+
+    let sun = def.sun.as_ref().map(|sun_def| {
+        let mut sun = DayCycle::new(sun_def.time);
+        sun.sun_intensity = sun_def.intensity.unwrap_or(sun.sun_intensity);
+        sun.moon_intensity = sun_def.moon_intensity.unwrap_or(sun.moon_intensity);
+        sun.tilt = sun_def.tilt.unwrap_or(sun.tilt);
+        sun.angular_radius = sun_def.angular_radius.unwrap_or(sun.angular_radius);
+        sun.samples = sun_def.samples.unwrap_or(sun.samples);
+        sun
+    });
+    let daylight = match (&sun, &def.daylight) {
+        (Some(_), Some(_)) => return Err(error_at(None, "[daylight] and [sun] cannot be used together".to_string())),
+        (Some(sun), None) => sun.light(),
+        (None, Some(daylight)) => light(daylight),
+        // An environment map can light the scene on its own
+        (None, None) if environment.is_some() => Light::new(Vec3::new(0.0, 1.0, 0.0), Color::black(), 0.0),
+        (None, None) => return Err(error_at(None, "missing [daylight] or [sun] section".to_string())),
+    };
+
+    let mut scene = Scene::new(objects, voxels);
+    scene.environment = environment;
+
+    Ok(LoadedScene {
+        scene,
+        textures,
+        lights: def.lights.iter().map(light).collect(),
+        daylight,
+        sun,
+        camera: Camera::new(vec3(def.camera.eye), vec3(def.camera.center), vec3(def.camera.up)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest complete scene; line 12 is the `spec` of its only material.
+    const BASE: &str = "\
+[camera]
+eye = [0.0, 0.0, 5.0]
+center = [0.0, 0.0, 0.0]
+
+[daylight]
+position = [0.0, 5.0, 0.0]
+color = [255, 255, 255]
+intensity = 1.0
+
+[materials.ice]
+diffuse = [200, 220, 255]
+spec = 50.0
+albedo = [0.9, 0.1]
+";
+
+    fn parse_error(source: &str) -> SceneError {
+        parse_scene(source, Path::new("scenes/broken.toml")).err().expect("the scene should be rejected")
+    }
+
+    #[test]
+    fn unknown_material() {
+        let source = format!("{}\n[[spheres]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"glass\"\n", BASE);
+        let error = parse_error(&source);
+        assert_eq!(error.line, Some(18));
+        assert_eq!(error.message, "unknown material 'glass'");
+        assert_eq!(error.to_string(), "scenes/broken.toml:18: unknown material 'glass'");
+    }
+
+    #[test]
+    fn wrong_type() {
+        let error = parse_error(&BASE.replace("spec = 50.0", "spec = \"shiny\""));
+        assert_eq!(error.line, Some(12));
+        assert!(error.message.contains("invalid type: string \"shiny\""), "{}", error.message);
+    }
+
+    #[test]
+    fn alpha_cutoff_without_texture() {
+        let error = parse_error(&format!("{}alpha_cutoff = 0.5\n", BASE));
+        assert_eq!(error.line, Some(14));
+        assert_eq!(error.message, "material 'ice' needs a texture to use alpha_cutoff");
+    }
+
+    #[test]
+    fn minimal_scene_parses() {
+        let loaded = parse_scene(BASE, Path::new("scenes/minimal.toml")).unwrap();
+        assert!(loaded.scene.objects().is_empty());
+        assert!(loaded.lights.is_empty());
+    }
+
+    #[test]
+    fn shipped_scenes_parse() {
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let diorama = load_scene(scenes.join("diorama.toml")).unwrap_or_else(|e| panic!("{}", e));
+        assert!(!diorama.scene.objects().is_empty());
+
+        // The Minecraft mapping only names materials and blocks of the diorama
+        let source = std::fs::read_to_string(scenes.join("diorama.toml")).unwrap();
+        let def: SceneDef = toml::from_str(&source).unwrap();
+        let mapping: BlockMappingDef = toml::from_str(&std::fs::read_to_string(scenes.join("minecraft.toml")).unwrap()).unwrap();
+        for name in mapping.default.iter().chain(mapping.blocks.values()) {
+            let name = name.get_ref();
+            assert!(def.materials.contains_key(name) || def.blocks.contains_key(name), "unknown target '{}'", name);
+        }
+    }
+}
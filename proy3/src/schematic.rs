@@ -0,0 +1,391 @@
+//! Minecraft builds: Sponge schematics (`.schem`, versions 2 and 3) and vanilla structure
+//! block files (`.nbt`).
+
+use std::path::Path;
+use crate::mesh::ImportError;
+use crate::nbt::{self, Tag};
+
+const VOID: &str = "minecraft:structure_void";
+const MALFORMED: &str = "missing or malformed block data";
+const MAX_VOLUME: usize = 1 << 28; // Cells a structure may have, far beyond any real build
+
+/// Blocks of a build by block state, such as `minecraft:furnace[facing=north,lit=false]`.
+///
+/// States are normalized with their properties sorted by name, see [`normalize_state`].
+#[derive(Debug, Clone)]
+pub struct Structure {
+    pub size: [usize; 3], // Blocks along x (east), y (up) and z (south)
+    pub palette: Vec<String>,
+    blocks: Vec<usize>, // Palette index of every cell, x fastest, then z, then y
+}
+
+impl Structure {
+    /// Reads a `.schem` or structure `.nbt` file, gzip compressed or not.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Structure, ImportError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| ImportError::new(path, e.to_string()))?;
+        Structure::from_nbt(&data).map_err(|message| ImportError::new(path, message))
+    }
+
+    /// Reads the contents of a `.schem` or structure `.nbt` file.
+    fn from_nbt(data: &[u8]) -> Result<Structure, String> {
+        let (_, root) = nbt::parse(data).map_err(|e| format!("invalid NBT: {}", e))?;
+
+        if root.get("Schematic").is_some() || root.get("Palette").is_some() {
+            Structure::from_sponge(&root)
+        } else if root.get("palette").is_some() || root.get("palettes").is_some() {
+            Structure::from_vanilla(&root)
+        } else {
+            Err("neither a Sponge schematic nor a structure file".to_string())
+        }
+    }
+
+    /// Index into `palette` of the block at a cell.
+    pub fn palette_index(&self, x: usize, y: usize, z: usize) -> usize {
+        self.blocks[(y * self.size[2] + z) * self.size[0] + x]
+    }
+
+    fn from_sponge(root: &Tag) -> Result<Structure, String> {
+        let malformed = || MALFORMED.to_string();
+        let schematic = root.get("Schematic").unwrap_or(root);
+        let blocks = schematic.get("Blocks").unwrap_or(schematic); // Version 3 nests them
+        // The format stores dimensions as unsigned shorts; wider integer tags must fit one too
+        let dimension = |name: &str| match schematic.get(name) {
+            Some(Tag::Short(v)) => Ok(*v as u16 as usize),
+            Some(tag) => tag
+                .as_i64()
+                .and_then(|v| u16::try_from(v).ok())
+                .map(usize::from)
+                .ok_or_else(|| format!("{} must be between 0 and {}", name, u16::MAX)),
+            None => Err(malformed()),
+        };
+        let size = [dimension("Width")?, dimension("Height")?, dimension("Length")?];
+        let volume = volume(size)?;
+
+        // Every index from 0 to the palette length must name exactly one state
+        let entries = blocks.get("Palette").and_then(Tag::as_compound).ok_or_else(malformed)?;
+        let mut palette = vec![None; entries.len()];
+        for (state, index) in entries {
+            let index = index.as_i64().ok_or_else(malformed)?;
+            let slot = usize::try_from(index)
+                .ok()
+                .and_then(|i| palette.get_mut(i))
+                .ok_or_else(|| format!("palette index {} of '{}' is out of range", index, state))?;
+            if let Some(other) = slot.replace(normalize_state(state)) {
+                return Err(format!("palette index {} is used by both '{}' and '{}'", index, other, state));
+            }
+        }
+        let palette: Vec<String> = palette.into_iter().flatten().collect();
+
+        let data = match blocks.get("Data").or_else(|| blocks.get("BlockData")) {
+            Some(Tag::ByteArray(data)) => data,
+            _ => return Err(malformed()),
+        };
+        // Palette indices are stored as LEB128 varints
+        let mut indices = Vec::with_capacity(volume.min(data.len()));
+        let (mut value, mut shift) = (0usize, 0);
+        for &byte in data {
+            value |= ((byte as u8 & 0x7f) as usize) << shift;
+            if byte as u8 & 0x80 == 0 {
+                if value >= palette.len() {
+                    return Err(format!("block data uses palette index {}, past the palette", value));
+                }
+                indices.push(value);
+                (value, shift) = (0, 0);
+            } else {
+                shift += 7;
+                if shift > 28 {
+                    return Err("block data holds an overlong varint".to_string());
+                }
+            }
+        }
+        if indices.len() != volume {
+            return Err(format!("block data has {} blocks for a {:?} structure", indices.len(), size));
+        }
+        Ok(Structure { size, palette, blocks: indices })
+    }
+
+    fn from_vanilla(root: &Tag) -> Result<Structure, String> {
+        let malformed = || MALFORMED.to_string();
+        let size_list = root.get("size").and_then(Tag::as_list).ok_or_else(malformed)?;
+        let dimension = |axis: usize| {
+            let value = size_list.get(axis).and_then(Tag::as_i64).ok_or_else(malformed)?;
+            usize::try_from(value).map_err(|_| format!("negative structure size {}", value))
+        };
+        let size = [dimension(0)?, dimension(1)?, dimension(2)?];
+        let volume = volume(size)?;
+
+        // Structures with random variants keep several palettes; the first one is the default
+        let palette_tag = match root.get("palette") {
+            Some(palette) => palette,
+            None => root.get("palettes").and_then(Tag::as_list).and_then(|p| p.first()).ok_or_else(malformed)?,
+        };
+        let mut palette = palette_tag
+            .as_list()
+            .ok_or_else(malformed)?
+            .iter()
+            .map(|entry| {
+                let name = entry.get("Name")?.as_str()?;
+                let properties = entry.get("Properties").and_then(Tag::as_compound);
+                let mut properties: Vec<String> = properties
+                    .map(|p| p.iter().filter_map(|(k, v)| Some(format!("{}={}", k, v.as_str()?))).collect())
+                    .unwrap_or_default();
+                properties.sort();
+                Some(if properties.is_empty() { name.to_string() } else { format!("{}[{}]", name, properties.join(",")) })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(malformed)?;
+
+        // Cells the file leaves out are not part of the structure
+        let void = palette.len();
+        palette.push(VOID.to_string());
+        let mut blocks = vec![void; volume];
+        for block in root.get("blocks").and_then(Tag::as_list).ok_or_else(malformed)? {
+            let cell = || {
+                let pos = block.get("pos")?.as_list()?;
+                let coordinate = |axis: usize| pos.get(axis)?.as_i64().and_then(|v| usize::try_from(v).ok());
+                let [x, y, z] = [coordinate(0)?, coordinate(1)?, coordinate(2)?];
+                let state = usize::try_from(block.get("state")?.as_i64()?).ok().filter(|&s| s < void)?;
+                if x >= size[0] || y >= size[1] || z >= size[2] {
+                    return None;
+                }
+                Some(((y * size[2] + z) * size[0] + x, state))
+            };
+            let (index, state) = cell().ok_or_else(malformed)?;
+            blocks[index] = state;
+        }
+        Ok(Structure { size, palette, blocks })
+    }
+}
+
+/// Number of cells in a structure of `size`, if it is small enough to load.
+fn volume(size: [usize; 3]) -> Result<usize, String> {
+    size[0]
+        .checked_mul(size[1])
+        .and_then(|area| area.checked_mul(size[2]))
+        .filter(|&volume| volume <= MAX_VOLUME)
+        .ok_or_else(|| format!("a {:?} structure is too large", size))
+}
+
+/// Sorts the properties of a block state so equal states compare equal as strings.
+pub fn normalize_state(state: &str) -> String {
+    match state.split_once('[') {
+        Some((name, properties)) => {
+            let mut properties: Vec<&str> = properties.trim_end_matches(']').split(',').map(str::trim).collect();
+            properties.sort_unstable();
+            format!("{}[{}]", name, properties.join(","))
+        }
+        None => state.to_string(),
+    }
+}
+
+/// Block name without its properties.
+pub fn block_name(state: &str) -> &str {
+    state.split_once('[').map_or(state, |(name, _)| name)
+}
+
+/// Returns true for states that leave the cell empty.
+pub fn is_air(state: &str) -> bool {
+    matches!(block_name(state), "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air" | VOID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tests::{compound, encode, encode_gzip};
+    use crate::scene_file::parse_scene;
+    use nalgebra_glm::Vec3;
+
+    /// 3×2×2 build on a palette of 200 states, so indices past 127 take two varint bytes.
+    fn palette_and_data() -> (Tag, Tag, Vec<usize>) {
+        let mut states: Vec<(String, Tag)> = (0..200).map(|i| (format!("minecraft:filler_{}", i), Tag::Int(i))).collect();
+        states[0].0 = "minecraft:air".to_string();
+        states[1].0 = "minecraft:furnace[lit=false,facing=south]".to_string();
+        let palette = Tag::Compound(states.into_iter().collect());
+
+        let indices = vec![0, 1, 199, 128, 127, 1, 0, 0, 150, 2, 3, 1];
+        let mut data = Vec::new();
+        for &index in &indices {
+            let mut value = index;
+            while value >= 0x80 {
+                data.push((value as u8 & 0x7f | 0x80) as i8);
+                value >>= 7;
+            }
+            data.push(value as i8);
+        }
+        (palette, Tag::ByteArray(data), indices)
+    }
+
+    fn assert_blocks(structure: &Structure, indices: &[usize]) {
+        assert_eq!(structure.size, [3, 2, 2]);
+        let mut cells = Vec::new();
+        for y in 0..2 {
+            for z in 0..2 {
+                for x in 0..3 {
+                    cells.push(structure.palette_index(x, y, z));
+                }
+            }
+        }
+        assert_eq!(cells, indices);
+        // Properties come back sorted
+        assert_eq!(structure.palette[1], "minecraft:furnace[facing=south,lit=false]");
+        assert_eq!(structure.palette[199], "minecraft:filler_199");
+    }
+
+    fn sponge_v2() -> Tag {
+        let (palette, data, _) = palette_and_data();
+        compound(vec![
+            ("Version", Tag::Int(2)),
+            ("Width", Tag::Short(3)),
+            ("Height", Tag::Short(2)),
+            ("Length", Tag::Short(2)),
+            ("Palette", palette),
+            ("BlockData", data),
+        ])
+    }
+
+    #[test]
+    fn reads_sponge_v2() {
+        let (_, _, indices) = palette_and_data();
+        let structure = Structure::from_nbt(&encode_gzip("Schematic", &sponge_v2())).unwrap();
+        assert_blocks(&structure, &indices);
+    }
+
+    #[test]
+    fn reads_sponge_v3() {
+        let (palette, data, indices) = palette_and_data();
+        let schematic = compound(vec![
+            ("Version", Tag::Int(3)),
+            ("Width", Tag::Short(3)),
+            ("Height", Tag::Short(2)),
+            ("Length", Tag::Short(2)),
+            ("Blocks", compound(vec![("Palette", palette), ("Data", data)])),
+        ]);
+        let root = compound(vec![("Schematic", schematic)]);
+        let structure = Structure::from_nbt(&encode("", &root)).unwrap();
+        assert_blocks(&structure, &indices);
+    }
+
+    #[test]
+    fn reads_vanilla_structure() {
+        let int_list = |values: [i32; 3]| Tag::List(values.map(Tag::Int).to_vec());
+        let state = |name: &str| compound(vec![("Name", Tag::String(name.to_string()))]);
+        let block = |pos: [i32; 3], state: i32| compound(vec![("pos", int_list(pos)), ("state", Tag::Int(state))]);
+        let root = compound(vec![
+            ("size", int_list([2, 1, 1])),
+            ("palette", Tag::List(vec![state("minecraft:lava"), state("minecraft:ice")])),
+            ("blocks", Tag::List(vec![block([1, 0, 0], 0)])),
+        ]);
+        let structure = Structure::from_nbt(&encode_gzip("", &root)).unwrap();
+        assert_eq!(structure.palette[structure.palette_index(1, 0, 0)], "minecraft:lava");
+        assert!(is_air(&structure.palette[structure.palette_index(0, 0, 0)])); // Left out of the file
+    }
+
+    #[test]
+    fn rejects_bad_varints_and_palettes() {
+        let with = |name: &str, tag: Tag| {
+            let Tag::Compound(mut children) = sponge_v2() else { unreachable!() };
+            children.insert(name.to_string(), tag);
+            Structure::from_nbt(&encode("Schematic", &Tag::Compound(children)))
+        };
+
+        // One block short, an index past the palette, and a varint that never ends
+        let Tag::ByteArray(data) = palette_and_data().1 else { unreachable!() };
+        assert!(with("BlockData", Tag::ByteArray(data[..data.len() - 1].to_vec())).is_err());
+        assert!(with("BlockData", Tag::ByteArray([&data[..data.len() - 1], &[-56, 1]].concat())).is_err());
+        assert!(with("BlockData", Tag::ByteArray(vec![-1; 12])).is_err());
+
+        // Palettes with a duplicate or a gap
+        let palette = |indices: [i32; 2]| compound(vec![("minecraft:air", Tag::Int(indices[0])), ("minecraft:ice", Tag::Int(indices[1]))]);
+        assert!(with("Palette", palette([0, 0])).unwrap_err().contains("used by both"));
+        assert!(with("Palette", palette([0, 2])).unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn rejects_huge_or_negative_sizes() {
+        let sponge = |dimension: Tag| {
+            let Tag::Compound(mut children) = sponge_v2() else { unreachable!() };
+            for name in ["Width", "Height", "Length"] {
+                children.insert(name.to_string(), dimension.clone());
+            }
+            Structure::from_nbt(&encode("Schematic", &Tag::Compound(children)))
+        };
+        // Unsigned shorts, so -1 is 65535 and the volume check has to catch it before allocating
+        assert!(sponge(Tag::Short(-1)).unwrap_err().contains("too large"));
+        assert!(sponge(Tag::Int(70000)).unwrap_err().contains("between 0 and 65535"));
+        assert!(sponge(Tag::Int(-3)).unwrap_err().contains("between 0 and 65535"));
+
+        let vanilla = |size: [i32; 3]| {
+            let root = compound(vec![
+                ("size", Tag::List(size.map(Tag::Int).to_vec())),
+                ("palette", Tag::List(vec![compound(vec![("Name", Tag::String("minecraft:ice".to_string()))])])),
+                ("blocks", Tag::List(Vec::new())),
+            ]);
+            Structure::from_nbt(&encode("", &root))
+        };
+        assert!(vanilla([i32::MAX, i32::MAX, i32::MAX]).unwrap_err().contains("too large"));
+        assert!(vanilla([1 << 20, 1 << 20, 1]).unwrap_err().contains("too large"));
+        assert!(vanilla([2, -1, 2]).unwrap_err().contains("negative"));
+        assert_eq!(vanilla([2, 0, 2]).unwrap().size, [2, 0, 2]);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = encode_gzip("Schematic", &sponge_v2());
+        assert!(Structure::from_nbt(&data[..data.len() - 10]).is_err());
+        let data = encode("Schematic", &sponge_v2());
+        assert!(Structure::from_nbt(&data[..data.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn reports_unmapped_blocks() {
+        let dir = std::env::temp_dir().join(format!("proy3-schematic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let palette = compound(vec![
+            ("minecraft:air", Tag::Int(0)),
+            ("minecraft:ice", Tag::Int(1)),
+            ("minecraft:stone", Tag::Int(2)),
+            ("minecraft:oak_stairs[facing=east]", Tag::Int(3)),
+        ]);
+        let root = compound(vec![
+            ("Width", Tag::Short(4)),
+            ("Height", Tag::Short(1)),
+            ("Length", Tag::Short(1)),
+            ("Palette", palette),
+            ("BlockData", Tag::ByteArray(vec![0, 1, 2, 3])),
+        ]);
+        std::fs::write(dir.join("hut.schem"), encode_gzip("Schematic", &root)).unwrap();
+        std::fs::write(dir.join("mapping.toml"), "[blocks]\n\"minecraft:ice\" = \"ice\"\n").unwrap();
+
+        let scene = r#"
+            [camera]
+            eye = [0.0, 0.0, 5.0]
+            center = [0.0, 0.0, 0.0]
+
+            [daylight]
+            position = [0.0, 5.0, 0.0]
+            color = [255, 255, 255]
+            intensity = 1.0
+
+            [materials.ice]
+            diffuse = [200, 220, 255]
+            spec = 50.0
+            albedo = [0.9, 0.1]
+
+            [[structures]]
+            path = "hut.schem"
+            mapping = "mapping.toml"
+            voxel_size = 1.0
+        "#;
+        let error = parse_scene(scene, &dir.join("scene.toml")).err().unwrap();
+        assert_eq!(error.path, dir.join("mapping.toml"));
+        assert_eq!(error.message, "no material for minecraft:oak_stairs, minecraft:stone (map them in [blocks] or set a default)");
+
+        std::fs::write(dir.join("mapping.toml"), "default = \"ice\"\n").unwrap();
+        let loaded = parse_scene(scene, &dir.join("scene.toml")).unwrap();
+        assert!(loaded.scene.closest_hit(&Vec3::new(3.5, 0.5, 5.0), &Vec3::new(0.0, 0.0, -1.0)).is_intersecting);
+        assert!(!loaded.scene.closest_hit(&Vec3::new(0.5, 0.5, 5.0), &Vec3::new(0.0, 0.0, -1.0)).is_intersecting);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use nalgebra_glm::Vec3;
+use std::f32::consts::PI;
+use crate::color::HdrColor;
+use crate::light::{orthonormal_basis, Light};
+
+const TURBIDITY: f32 = 2.5;      // Haze of the atmosphere; 2 is very clear, 10 is hazy
+const LUMINANCE_SCALE: f32 = 0.045; // Converts the model's kcd/m² into the renderer's light units
+const AMBIENT_SAMPLES: usize = 8;  // Per axis, for each face of the ambient cube
+
+/// Analytic daylight sky after Preetham, Shirley and Smits (1999).
+///
+/// The sky is lit by the main light: its direction places the sun and its intensity scales
+/// the whole dome, so the moon gives a dim night sky.
+#[derive(Debug, Clone)]
+pub struct Sky {
+    sun_direction: Vec3,
+    scale: f32,
+    zenith: [f32; 3],       // Y, x, y at the zenith
+    perez: [[f32; 5]; 3],   // Distribution coefficients A..E for Y, x and y
+    perez_zenith: [f32; 3], // F(0, θs) of each channel, normalizes the distribution
+    ambient: [HdrColor; 6], // Cosine-weighted sky light arriving from +X, -X, +Y, -Y, +Z, -Z
+}
+
+impl Sky {
+    /// Builds the sky for a sun in `sun_direction` (towards the sun) scaled by `intensity`.
+    pub fn new(sun_direction: Vec3, intensity: f32) -> Self {
+        let mut sun_direction = sun_direction.normalize();
+        // The model breaks down below the horizon, keep the sun just above it
+        sun_direction.y = sun_direction.y.max(0.02);
+        let sun_direction = sun_direction.normalize();
+
+        let t = TURBIDITY;
+        let theta_s = sun_direction.y.acos();
+        let (theta2, theta3) = (theta_s * theta_s, theta_s * theta_s * theta_s);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta_s)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta_s)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta_s + 0.26688);
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let perez_zenith = perez.map(|c| perez_function(&c, 1.0, theta_s.cos()));
+
+        let mut sky = Sky {
+            sun_direction,
+            scale: LUMINANCE_SCALE * intensity.max(0.0),
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez,
+            perez_zenith,
+            ambient: [HdrColor::black(); 6],
+        };
+        sky.ambient = AXES.map(|axis| sky.integrate_hemisphere(&axis));
+        sky
+    }
+
+    /// Sky lit by the main light; point lights are treated as a sun in their direction.
+    pub fn from_light(light: &Light) -> Self {
+        Sky::new(light.direction_from(&Vec3::zeros()), light.intensity)
+    }
+
+    /// Light arriving from `direction`; below the horizon the sky fades into darker ground.
+    pub fn radiance(&self, direction: &Vec3) -> HdrColor {
+        let direction = direction.normalize();
+        let cos_theta = direction.y.max(0.01);
+        let cos_gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0);
+
+        let values: [f32; 3] = std::array::from_fn(|channel| {
+            let f = perez_function(&self.perez[channel], cos_theta, cos_gamma);
+            self.zenith[channel] * f / self.perez_zenith[channel]
+        });
+        let color = xyy_to_linear_rgb(values[1], values[2], values[0]) * self.scale;
+
+        if direction.y < 0.0 {
+            // Diffuse ground reflecting part of the horizon
+            let ground = (1.0 + direction.y * 2.0).max(0.3);
+            color * ground
+        } else {
+            color
+        }
+    }
+
+    /// Average sky light reaching a surface facing `normal`, weighted by the cosine.
+    ///
+    /// Multiplied by a diffuse albedo this gives the reflected ambient light.
+    pub fn ambient(&self, normal: &Vec3) -> HdrColor {
+        let n = normal.normalize();
+        let pick = |value: f32, positive: usize| if value >= 0.0 { self.ambient[positive] } else { self.ambient[positive + 1] };
+        pick(n.x, 0) * (n.x * n.x) + pick(n.y, 2) * (n.y * n.y) + pick(n.z, 4) * (n.z * n.z)
+    }
+
+    fn integrate_hemisphere(&self, normal: &Vec3) -> HdrColor {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let mut sum = HdrColor::black();
+        for j in 0..AMBIENT_SAMPLES {
+            for i in 0..AMBIENT_SAMPLES {
+                // Stratified cosine-weighted directions
+                let u = (i as f32 + 0.5) / AMBIENT_SAMPLES as f32;
+                let v = (j as f32 + 0.5) / AMBIENT_SAMPLES as f32;
+                let r = v.sqrt();
+                let phi = 2.0 * PI * u;
+                let direction = tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - v).sqrt();
+                sum += self.radiance(&direction);
+            }
+        }
+        sum * (1.0 / (AMBIENT_SAMPLES * AMBIENT_SAMPLES) as f32)
+    }
+}
+
+/// Directions of the ambient cube faces.
+pub(crate) const AXES: [Vec3; 6] = [
+    Vec3::new(1.0, 0.0, 0.0),
+    Vec3::new(-1.0, 0.0, 0.0),
+    Vec3::new(0.0, 1.0, 0.0),
+    Vec3::new(0.0, -1.0, 0.0),
+    Vec3::new(0.0, 0.0, 1.0),
+    Vec3::new(0.0, 0.0, -1.0),
+];
+
+/// Perez et al. luminance distribution for a view at zenith angle θ and angle γ to the sun.
+fn perez_function(c: &[f32; 5], cos_theta: f32, cos_gamma: f32) -> f32 {
+    let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+fn xyy_to_linear_rgb(x: f32, y: f32, luminance: f32) -> HdrColor {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    HdrColor::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
@@ -0,0 +1,88 @@
+use nalgebra_glm::Vec3;
+use std::f32::consts::PI;
+use crate::color::{Color, HdrColor};
+use crate::light::{Light, LightShape};
+
+const SUNRISE: f32 = 6.0; // Hour the sun crosses the eastern horizon; it sets twelve hours later
+
+/// Simulated clock that drives a directional sun and, at night, a dimmer moon.
+///
+/// The sun travels along a circle from +X (east) through the sky to -X, tilted towards +Z by
+/// `tilt`; the moon is always on the opposite side.
+#[derive(Debug, Clone)]
+pub struct DayCycle {
+    pub time: f32,           // Hours since midnight, wraps at 24
+    pub sun_intensity: f32,  // Intensity at noon
+    pub moon_intensity: f32, // Intensity with the moon overhead
+    pub tilt: f32,           // Radians the sun's path leans away from the zenith
+    pub angular_radius: f32, // Apparent radius of the sun and moon, controls shadow softness
+    pub samples: u32,        // Shadow rays per point
+}
+
+impl DayCycle {
+    pub fn new(time: f32) -> Self {
+        DayCycle {
+            time: time.rem_euclid(24.0),
+            sun_intensity: 1.0,
+            moon_intensity: 0.08,
+            tilt: 0.5,
+            angular_radius: 0.05,
+            samples: 8,
+        }
+    }
+
+    /// Moves the clock by `hours` (negative goes back in time).
+    pub fn advance(&mut self, hours: f32) {
+        self.time = (self.time + hours).rem_euclid(24.0);
+    }
+
+    /// Unit vector pointing at the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.time - SUNRISE) / 24.0 * 2.0 * PI;
+        Vec3::new(angle.cos(), angle.sin() * self.tilt.cos(), angle.sin() * self.tilt.sin()).normalize()
+    }
+
+    /// Height of the sun over the horizon, from -1 (below the feet) to 1 (zenith).
+    pub fn sun_elevation(&self) -> f32 {
+        self.sun_direction().y
+    }
+
+    /// The light of the current hour: the sun by day, the moon by night.
+    pub fn light(&self) -> Light {
+        let elevation = self.sun_elevation();
+        let (direction, color, intensity) = if elevation > -0.05 {
+            // Warm and dim near the horizon, white at noon
+            let kelvin = 1900.0 + 3900.0 * smoothstep(-0.05, 0.6, elevation);
+            let intensity = self.sun_intensity * smoothstep(-0.05, 0.15, elevation);
+            (self.sun_direction(), color_temperature(kelvin), intensity)
+        } else {
+            let intensity = self.moon_intensity * smoothstep(0.05, 0.25, -elevation);
+            (-self.sun_direction(), color_temperature(7500.0), intensity)
+        };
+
+        Light::new(direction * 1000.0, color, intensity).with_shape(
+            LightShape::Directional { direction, angular_radius: self.angular_radius },
+            self.samples,
+        )
+    }
+}
+
+/// Approximate color of a black body at `kelvin`, after Tanner Helland's fit.
+pub fn color_temperature(kelvin: f32) -> HdrColor {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.7 * (t - 60.0).powf(-0.1332) };
+    let g = if t <= 66.0 { 99.47 * t.ln() - 161.12 } else { 288.12 * (t - 60.0).powf(-0.0755) };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.52 * (t - 10.0).ln() - 305.04
+    };
+    Color::new(r.clamp(0.0, 255.0) as i32, g.clamp(0.0, 255.0) as i32, b.clamp(0.0, 255.0) as i32).into()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -1,34 +1,107 @@
 extern crate image;
 
-use image::{ImageReader, DynamicImage, GenericImageView};
+use image::{ImageReader, DynamicImage, GenericImageView, ImageResult};
 use std::fmt;
-use crate::color::Color;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use crate::color::{Color, HdrColor};
+
+/// How texels are reconstructed when sampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    Nearest,  // Single texel from the full resolution image
+    Bilinear, // Blend of the four closest texels from the full resolution image
+    #[default]
+    Trilinear, // Bilinear samples of the two mip levels matching the footprint, blended
+}
+
+/// How texture coordinates outside [0, 1] are mapped back onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    #[default]
+    Repeat, // Tile the image
+    Clamp,  // Stretch the edge texels
+    Mirror, // Tile the image, flipping every other copy
+}
+
+/// Filtering and wrapping settings used to look up a texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sampler {
+    pub filter: FilterMode,
+    pub wrap: WrapMode,
+}
+
+/// Downscaled copy of a texture.
+#[derive(Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<HdrColor>,
+}
+
+/// Coverage of every texel, read by alpha-tested materials while tracing rays.
+#[derive(Debug, Clone)]
+pub struct AlphaMask {
+    width: usize,
+    height: usize,
+    alpha: Vec<f32>, // 0 is a hole, 1 is solid
+}
+
+impl AlphaMask {
+    /// Reads a standalone opacity map, such as an MTL `map_d`: its alpha channel when the
+    /// image has one, its grey level otherwise.
+    pub fn load<P: AsRef<Path>>(file_path: P) -> ImageResult<AlphaMask> {
+        let img = ImageReader::open(file_path)?.decode()?;
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let alpha = if img.color().has_alpha() {
+            img.to_rgba8().pixels().map(|p| p[3] as f32 / 255.0).collect()
+        } else {
+            img.to_luma8().pixels().map(|p| p[0] as f32 / 255.0).collect()
+        };
+        Ok(AlphaMask { width, height, alpha })
+    }
+
+    /// Alpha of the texel under `(u, v)`, with `v` pointing up the image.
+    pub fn sample(&self, u: f32, v: f32, wrap: WrapMode) -> f32 {
+        let x = wrap_texel((u * self.width as f32).floor() as i64, self.width, wrap);
+        let y = wrap_texel(((1.0 - v) * self.height as f32).floor() as i64, self.height, wrap);
+        self.alpha[y * self.width + x]
+    }
+}
 
 #[derive(Clone)]
 pub struct Texture {
     image: DynamicImage,
     pub width: usize,
     pub height: usize,
-    color_array: Vec<Color>,
+    color_array: Vec<HdrColor>, // Linear colors, decoded from the sRGB image
+    mips: Vec<MipLevel>, // Level 1 onwards, each half the size of the previous one
+    alpha: Arc<AlphaMask>, // Shared with the materials that cut holes with it
 }
 
 impl Texture {
     /// Creates a new Texture from the given file path.
     pub fn new(file_path: &str) -> Texture {
-        let img = match ImageReader::open(file_path) {
-            Ok(reader) => match reader.decode() {
-                Ok(image) => image,
-                Err(e) => {
-                    println!("Error decoding image: {}", e);
-                    return Texture::black(); // Use a black texture as a fallback
-                }
-            },
+        match Texture::load(file_path) {
+            Ok(texture) => texture,
             Err(e) => {
-                println!("Error opening image file: {}", e);
-                return Texture::black(); // Use a black texture as a fallback
+                println!("Error loading image: {}", e);
+                Texture::black() // Use a black texture as a fallback
             }
-        };
+        }
+    }
+
+    /// Loads a texture, reporting files that cannot be opened or decoded.
+    pub fn load<P: AsRef<Path>>(file_path: P) -> ImageResult<Texture> {
+        let img = ImageReader::open(file_path)?.decode()?;
+        Ok(Texture::from_image(img))
+    }
 
+    /// Creates a texture from an already decoded image.
+    pub fn from_image(img: DynamicImage) -> Texture {
         let width = img.width() as usize;
         let height = img.height() as usize;
 
@@ -36,16 +109,19 @@ impl Texture {
             image: img,
             width,
             height,
-            color_array: vec![Color::black(); width * height],
+            color_array: vec![HdrColor::black(); width * height],
+            mips: Vec::new(),
+            alpha: Arc::new(AlphaMask { width, height, alpha: vec![1.0; width * height] }),
         };
 
         texture.load_color_array();
+        texture.generate_mips();
         texture
     }
 
-    /// Loads the color array from the image.
-    /// Loads the color array from the image.
+    /// Loads the color array and the alpha mask from the image.
     fn load_color_array(&mut self) {
+        let mut alpha = vec![1.0; self.width * self.height];
         for x in 0..self.width {
             for y in 0..self.height {
                 // Get the pixel as Rgba<u8>
@@ -58,16 +134,127 @@ impl Texture {
 
                 // Combine the channels into a color and store it
                 let color = ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
-                self.color_array[y * self.width + x] = Color::from_hex(color);
+                self.color_array[y * self.width + x] = Color::from_hex(color).into(); // Decode sRGB
+                alpha[y * self.width + x] = pixel[3] as f32 / 255.0; // Opaque images report 255
+            }
+        }
+        self.alpha = Arc::new(AlphaMask { width: self.width, height: self.height, alpha });
+    }
+
+
+    /// Builds the mip chain with a 2x2 box filter, down to a single texel.
+    fn generate_mips(&mut self) {
+        self.mips.clear();
+        let (mut width, mut height) = (self.width, self.height);
+
+        while width > 1 || height > 1 {
+            let next_width = (width / 2).max(1);
+            let next_height = (height / 2).max(1);
+            let source = self.mips.last().map_or(&self.color_array, |level| &level.texels);
+
+            let mut texels = Vec::with_capacity(next_width * next_height);
+            for y in 0..next_height {
+                for x in 0..next_width {
+                    let (x0, y0) = (2 * x, 2 * y);
+                    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+                    let corners = [
+                        source[y0 * width + x0],
+                        source[y0 * width + x1],
+                        source[y1 * width + x0],
+                        source[y1 * width + x1],
+                    ];
+                    // Averaging in linear space keeps distant textures from darkening
+                    let sum = corners.iter().fold(HdrColor::black(), |acc, &c| acc + c);
+                    texels.push(sum * 0.25);
+                }
+            }
+
+            self.mips.push(MipLevel { width: next_width, height: next_height, texels });
+            width = next_width;
+            height = next_height;
+        }
+    }
+
+    /// Number of mip levels, including the full resolution image.
+    pub fn mip_levels(&self) -> usize {
+        self.mips.len() + 1
+    }
+
+    fn level(&self, level: usize) -> (usize, usize, &[HdrColor]) {
+        match level {
+            0 => (self.width, self.height, &self.color_array),
+            _ => {
+                let mip = &self.mips[level - 1];
+                (mip.width, mip.height, &mip.texels)
             }
         }
     }
 
+    /// Samples the texture at `(u, v)`, with `v` pointing up the image.
+    ///
+    /// `footprint` is the size of the area seen by the ray in UV units; trilinear
+    /// filtering uses it to pick the mip levels.
+    pub fn sample(&self, sampler: &Sampler, u: f32, v: f32, footprint: f32) -> HdrColor {
+        let t = 1.0 - v; // Image rows are stored top to bottom
 
-    /// Returns the color at the specified pixel coordinates.
-    pub fn get_color(&self, x: usize, y: usize) -> Color {
+        match sampler.filter {
+            FilterMode::Nearest => self.sample_nearest(0, u, t, sampler.wrap),
+            FilterMode::Bilinear => self.sample_bilinear(0, u, t, sampler.wrap),
+            FilterMode::Trilinear => {
+                let texels = footprint * self.width.max(self.height) as f32;
+                let lod = texels.max(1.0).log2().min((self.mip_levels() - 1) as f32);
+                let lower = lod.floor() as usize;
+                let blend = lod - lower as f32;
+
+                let color = self.sample_bilinear(lower, u, t, sampler.wrap);
+                if blend <= 0.0 {
+                    return color;
+                }
+                lerp_color(&color, &self.sample_bilinear(lower + 1, u, t, sampler.wrap), blend)
+            }
+        }
+    }
+
+    fn sample_nearest(&self, level: usize, u: f32, t: f32, wrap: WrapMode) -> HdrColor {
+        let (width, height, texels) = self.level(level);
+        let x = wrap_texel((u * width as f32).floor() as i64, width, wrap);
+        let y = wrap_texel((t * height as f32).floor() as i64, height, wrap);
+        texels[y * width + x]
+    }
+
+    fn sample_bilinear(&self, level: usize, u: f32, t: f32, wrap: WrapMode) -> HdrColor {
+        let (width, height, texels) = self.level(level);
+
+        // Texel centers sit at half-integer coordinates
+        let x = u * width as f32 - 0.5;
+        let y = t * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let left = wrap_texel(x0 as i64, width, wrap);
+        let right = wrap_texel(x0 as i64 + 1, width, wrap);
+        let top = wrap_texel(y0 as i64, height, wrap);
+        let bottom = wrap_texel(y0 as i64 + 1, height, wrap);
+
+        let upper = lerp_color(&texels[top * width + left], &texels[top * width + right], fx);
+        let lower = lerp_color(&texels[bottom * width + left], &texels[bottom * width + right], fx);
+        lerp_color(&upper, &lower, fy)
+    }
+
+    /// Returns true if any texel is not fully opaque.
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.alpha.iter().any(|&a| a < 1.0)
+    }
+
+    /// Coverage of the texels, shared so materials can test it without the registry.
+    pub fn alpha_mask(&self) -> Arc<AlphaMask> {
+        Arc::clone(&self.alpha)
+    }
+
+    /// Returns the linear color at the specified pixel coordinates.
+    pub fn get_color(&self, x: usize, y: usize) -> HdrColor {
         if x >= self.width || y >= self.height {
-            Color::from_hex(0xFF00FF) // Return magenta if out of bounds
+            Color::from_hex(0xFF00FF).into() // Return magenta if out of bounds
         } else {
             self.color_array[y * self.width + x]
         }
@@ -82,7 +269,9 @@ impl Texture {
             image: DynamicImage::new_rgb8(width as u32, height as u32),
             width,
             height,
-            color_array: vec![Color::new(0, 0, 0); width * height], // Black colors
+            color_array: vec![HdrColor::black(); width * height], // Black colors
+            mips: Vec::new(),
+            alpha: Arc::new(AlphaMask { width, height, alpha: vec![1.0; width * height] }),
         };
 
         texture.load_color_array(); // Load the black color
@@ -90,11 +279,107 @@ impl Texture {
     }
 }
 
+/// Maps a possibly out-of-range texel index into `0..size`.
+fn wrap_texel(index: i64, size: usize, wrap: WrapMode) -> usize {
+    let size = size as i64;
+    let wrapped = match wrap {
+        WrapMode::Repeat => index.rem_euclid(size),
+        WrapMode::Clamp => index.clamp(0, size - 1),
+        WrapMode::Mirror => {
+            let period = index.rem_euclid(2 * size);
+            if period < size { period } else { 2 * size - 1 - period }
+        }
+    };
+    wrapped as usize
+}
+
+fn lerp_color(a: &HdrColor, b: &HdrColor, t: f32) -> HdrColor {
+    *a * (1.0 - t) + *b * t
+}
+
 impl fmt::Debug for Texture {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Texture")
             .field("width", &self.width)
             .field("height", &self.height)
+            .field("mip_levels", &self.mip_levels())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// Texture from 8-bit sRGB values given row by row, top row first.
+    fn texture(width: u32, height: u32, values: &[u8]) -> Texture {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            let value = values[(y * width + x) as usize];
+            Rgba([value, value, value, 255])
+        });
+        Texture::from_image(DynamicImage::ImageRgba8(image))
+    }
+
+    fn nearest(wrap: WrapMode) -> Sampler {
+        Sampler { filter: FilterMode::Nearest, wrap }
+    }
+
+    #[test]
+    fn wrap_modes() {
+        // One row of four texels, black to white; u = -0.125 is half a texel left of the image
+        let ramp = texture(4, 1, &[0, 85, 170, 255]);
+        let value = |wrap: WrapMode, u: f32| ramp.sample(&nearest(wrap), u, 0.5, 0.0).r;
+        let texel = |x: usize| ramp.get_color(x, 0).r;
+
+        assert_eq!(value(WrapMode::Repeat, -0.125), texel(3));
+        assert_eq!(value(WrapMode::Repeat, 1.375), texel(1));
+        assert_eq!(value(WrapMode::Clamp, -0.125), texel(0));
+        assert_eq!(value(WrapMode::Clamp, 7.0), texel(3));
+        assert_eq!(value(WrapMode::Mirror, -0.125), texel(0));
+        assert_eq!(value(WrapMode::Mirror, 1.125), texel(3));
+        assert_eq!(value(WrapMode::Mirror, 1.875), texel(0));
+        assert_eq!(value(WrapMode::Mirror, 2.125), texel(0)); // The third copy faces forward again
+
+        for x in [-9, -1, 0, 3, 4, 11] {
+            assert!(wrap_texel(x, 4, WrapMode::Repeat) < 4 && wrap_texel(x, 4, WrapMode::Mirror) < 4);
+        }
+    }
+
+    #[test]
+    fn bilinear_blends_neighbours() {
+        let pair = texture(2, 1, &[0, 255]);
+        let bilinear = Sampler { filter: FilterMode::Bilinear, wrap: WrapMode::Clamp };
+        let (black, white) = (pair.get_color(0, 0).r, pair.get_color(1, 0).r);
+
+        // Halfway between the two texel centers, and exactly on each center
+        assert!((pair.sample(&bilinear, 0.5, 0.5, 0.0).r - (black + white) / 2.0).abs() < 1e-6);
+        assert!((pair.sample(&bilinear, 0.25, 0.5, 0.0).r - black).abs() < 1e-6);
+        assert!((pair.sample(&bilinear, 0.75, 0.5, 0.0).r - white).abs() < 1e-6);
+        assert!((pair.sample(&bilinear, 0.375, 0.5, 0.0).r - (0.75 * black + 0.25 * white)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mip_chain() {
+        // 4×2 image of alternating black and white columns
+        let stripes = texture(4, 2, &[0, 255, 0, 255, 0, 255, 0, 255]);
+        assert_eq!(stripes.mip_levels(), 3);
+        let sizes: Vec<(usize, usize)> = (0..3).map(|level| (stripes.level(level).0, stripes.level(level).1)).collect();
+        assert_eq!(sizes, [(4, 2), (2, 1), (1, 1)]);
+
+        // Averaged in linear light: half of white, not the sRGB midpoint
+        for &texel in stripes.level(1).2.iter().chain(stripes.level(2).2) {
+            assert!((texel.r - 0.5).abs() < 1e-6);
+        }
+
+        // A footprint covering the whole image reads the last level
+        let trilinear = Sampler { filter: FilterMode::Trilinear, wrap: WrapMode::Repeat };
+        assert!((stripes.sample(&trilinear, 0.3, 0.6, 1.0).r - 0.5).abs() < 1e-6);
+        assert_eq!(stripes.sample(&trilinear, 0.125, 0.5, 0.0).r, stripes.get_color(0, 0).r);
+
+        // Odd sizes round down and stop at one texel
+        let odd = texture(5, 3, &[128; 15]);
+        let sizes: Vec<(usize, usize)> = (0..odd.mip_levels()).map(|level| (odd.level(level).0, odd.level(level).1)).collect();
+        assert_eq!(sizes, [(5, 3), (2, 1), (1, 1)]);
+    }
+}
@@ -0,0 +1,66 @@
+use image::ImageResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::texture::Texture;
+
+/// Handle to a texture stored in a `TextureRegistry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// Owns every texture of a scene and hands out handles that materials refer to.
+#[derive(Debug, Default)]
+pub struct TextureRegistry {
+    textures: Vec<Texture>,
+    by_path: HashMap<PathBuf, TextureHandle>,
+    by_name: HashMap<String, TextureHandle>,
+}
+
+impl TextureRegistry {
+    pub fn new() -> Self {
+        TextureRegistry::default()
+    }
+
+    /// Loads the image at `path`, reusing the existing handle if that file was loaded before.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> ImageResult<TextureHandle> {
+        let path = path.as_ref();
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(&handle) = self.by_path.get(&key) {
+            return Ok(handle);
+        }
+
+        let handle = self.insert(Texture::load(path)?);
+        self.by_path.insert(key, handle);
+        Ok(handle)
+    }
+
+    /// Loads the image at `path` and makes it available under `name`.
+    pub fn load_named<P: AsRef<Path>>(&mut self, name: &str, path: P) -> ImageResult<TextureHandle> {
+        let handle = self.load(path)?;
+        self.by_name.insert(name.to_string(), handle);
+        Ok(handle)
+    }
+
+    /// Adds a texture that was not read from disk.
+    pub fn insert(&mut self, texture: Texture) -> TextureHandle {
+        self.textures.push(texture);
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// Looks up a texture registered with `load_named`.
+    pub fn find(&self, name: &str) -> Option<TextureHandle> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn get(&self, handle: TextureHandle) -> &Texture {
+        &self.textures[handle.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
@@ -0,0 +1,87 @@
+use crate::color::{Color, HdrColor};
+
+/// Curve that compresses linear radiance into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapOperator {
+    Clamp,    // Cut everything above 1.0
+    Reinhard, // x / (1 + x), never fully saturates
+    #[default]
+    AcesFilmic, // Narkowicz's fit of the ACES reference curve
+}
+
+impl ToneMapOperator {
+    /// Cycles through the operators, for the viewer's toggle key.
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Clamp => ToneMapOperator::Reinhard,
+            ToneMapOperator::Reinhard => ToneMapOperator::AcesFilmic,
+            ToneMapOperator::AcesFilmic => ToneMapOperator::Clamp,
+        }
+    }
+
+    fn map(self, x: f32) -> f32 {
+        match self {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::AcesFilmic => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                ((x * (a * x + b)) / (x * (c * x + d) + e)).min(1.0) // The fit overshoots 1 past x ≈ 7.2
+            }
+        }
+    }
+}
+
+/// Post-process settings turning accumulated radiance into display colors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f32, // In stops; every +1 doubles the brightness
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator, exposure: f32) -> Self {
+        ToneMapping { operator, exposure }
+    }
+
+    /// Applies exposure and the operator, then encodes the result as sRGB.
+    pub fn apply(&self, radiance: HdrColor) -> Color {
+        let exposed = radiance * self.exposure.exp2();
+        HdrColor::new(
+            self.operator.map(exposed.r.max(0.0)),
+            self.operator.map(exposed.g.max(0.0)),
+            self.operator.map(exposed.b.max(0.0)),
+        )
+        .to_color()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators() {
+        assert_eq!(ToneMapOperator::Clamp.map(0.25), 0.25);
+        assert_eq!(ToneMapping::new(ToneMapOperator::Clamp, 0.0).apply(HdrColor::new(4.0, 1.0, 0.0)).r, 255);
+        assert_eq!(ToneMapOperator::Reinhard.map(1.0), 0.5);
+        assert_eq!(ToneMapOperator::Reinhard.map(3.0), 0.75);
+        assert!(ToneMapOperator::AcesFilmic.map(0.0).abs() < 1e-6);
+
+        // Every curve rises steadily and stays displayable
+        for operator in [ToneMapOperator::Reinhard, ToneMapOperator::AcesFilmic] {
+            let mut previous = 0.0;
+            for i in 1..=2000 {
+                let value = operator.map(i as f32 * 0.05);
+                assert!(value >= previous && value <= 1.0, "{:?} at {}", operator, i as f32 * 0.05);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let mapping = ToneMapping::new(ToneMapOperator::Reinhard, 1.0);
+        let color = mapping.apply(HdrColor::new(0.5, 0.5, 0.5));
+        assert_eq!(color.r, ToneMapping::new(ToneMapOperator::Reinhard, 0.0).apply(HdrColor::new(1.0, 1.0, 1.0)).r);
+    }
+}
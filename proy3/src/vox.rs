@@ -0,0 +1,495 @@
+//! MagicaVoxel `.vox` import.
+//!
+//! Every model instance of the file's scene graph (`nTRN`/`nGRP`/`nSHP` nodes, including their
+//! translations and 90° rotations) becomes one `VoxelWorld`. Palette entries become block types,
+//! with `MATL` chunks mapped onto `Material`:
+//!
+//! - `_metal` materials reflect with Schlick's Fresnel; their `_metal` value is the reflectance
+//! - `_glass` refracts with `_trans` transparency and `_ior`
+//! - `_emit` glows with `_emit * (1 + _flux)` times the palette color
+//! - `_rough` sets the shininess of the highlight and dims metal reflections
+//!
+//! Files without a scene graph have their models laid out side by side along X. Without an
+//! `RGBA` chunk colors come from MagicaVoxel's default palette. MagicaVoxel's Z axis points up;
+//! it becomes the renderer's Y axis.
+
+use nalgebra_glm::Vec3;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::color::{Color, HdrColor};
+use crate::material::{FresnelMode, Material};
+use crate::mesh::ImportError;
+use crate::voxel::{BlockId, VoxelWorld};
+
+type Dict = HashMap<String, String>;
+
+const BROKEN_GRAPH: &str = "broken scene graph";
+const OUT_OF_RANGE: &str = "voxel positions out of range";
+
+/// Rotation (a signed permutation, row major) and translation in voxel units.
+#[derive(Debug, Clone, Copy)]
+struct Transform {
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    /// Transformed point, or None if a coordinate leaves the range of `i32`.
+    fn apply(&self, v: [i32; 3]) -> Option<[i32; 3]> {
+        let mut result = [0; 3];
+        for (row, coordinate) in result.iter_mut().enumerate() {
+            *coordinate = (0..3).try_fold(self.translation[row], |sum, col| sum.checked_add(self.rotation[row][col].checked_mul(v[col])?))?;
+        }
+        Some(result)
+    }
+
+    /// `self` applied after `inner`, or None if the translations overflow.
+    fn then(&self, inner: &Transform) -> Option<Transform> {
+        let rotation = std::array::from_fn(|row| {
+            std::array::from_fn(|col| (0..3).map(|k| self.rotation[row][k] * inner.rotation[k][col]).sum())
+        });
+        Some(Transform { rotation, translation: self.apply(inner.translation)? })
+    }
+}
+
+struct Model {
+    size: [i32; 3],
+    voxels: Vec<([u8; 3], u8)>, // Position and palette index
+}
+
+enum Node {
+    Transform { child: i32, transform: Transform, hidden: bool },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+/// Loads every visible model of a `.vox` file as a voxel grid of `voxel_size` cubes, with the
+/// file's origin placed at `position`.
+pub fn load_vox<P: AsRef<Path>>(path: P, position: Vec3, voxel_size: f32) -> Result<Vec<VoxelWorld>, ImportError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| ImportError::new(path, e.to_string()))?;
+    parse_vox(&data, path, position, voxel_size)
+}
+
+/// Builds the grids of a `.vox` file's contents; `path` only labels errors.
+fn parse_vox(data: &[u8], path: &Path, position: Vec3, voxel_size: f32) -> Result<Vec<VoxelWorld>, ImportError> {
+    let error = |message: &str| ImportError::new(path, message);
+
+    let mut reader = Reader { data, offset: 0 };
+    if reader.bytes(4) != Some(b"VOX ") {
+        return Err(error("not a MagicaVoxel file"));
+    }
+    reader.i32().ok_or_else(|| error("truncated header"))?; // Version
+    if reader.bytes(4) != Some(b"MAIN") {
+        return Err(error("missing MAIN chunk"));
+    }
+    reader.bytes(8).ok_or_else(|| error("truncated MAIN chunk"))?; // Sizes; children fill the rest
+
+    let mut models = Vec::new();
+    let mut pending_size = None;
+    let mut nodes = HashMap::new();
+    let mut palette: Option<Vec<[u8; 4]>> = None;
+    let mut materials: HashMap<u8, Dict> = HashMap::new();
+
+    while reader.offset < data.len() {
+        let (id, content) = reader.chunk().ok_or_else(|| error("truncated chunk"))?;
+        let mut chunk = Reader { data: content, offset: 0 };
+        let parsed = match id {
+            b"SIZE" => chunk.i32_array().map(|size| pending_size = Some(size)),
+            b"XYZI" => chunk.voxels().map(|voxels| {
+                models.push(Model { size: pending_size.take().unwrap_or([0; 3]), voxels });
+            }),
+            b"nTRN" => chunk.transform_node().map(|(id, node)| {
+                nodes.insert(id, node);
+            }),
+            b"nGRP" => chunk.group_node().map(|(id, node)| {
+                nodes.insert(id, node);
+            }),
+            b"nSHP" => chunk.shape_node().map(|(id, node)| {
+                nodes.insert(id, node);
+            }),
+            b"RGBA" => chunk.bytes(1024).map(|bytes| {
+                palette = Some(bytes.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect());
+            }),
+            b"MATL" => chunk.i32().zip(chunk.dict()).map(|(id, dict)| {
+                if (1..=255).contains(&id) {
+                    materials.insert(id as u8, dict);
+                }
+            }),
+            _ => Some(()), // Layers, cameras, render settings and notes are not used
+        };
+        parsed.ok_or_else(|| error(&format!("malformed {} chunk", String::from_utf8_lossy(id))))?;
+    }
+    if models.is_empty() {
+        return Err(error("the file has no models"));
+    }
+
+    let mut instances = Vec::new();
+    if nodes.contains_key(&0) {
+        collect_instances(&nodes, 0, &Transform::IDENTITY, &mut instances, 0).map_err(error)?;
+    } else {
+        // Without a scene graph, models sit in a row from the origin, one voxel apart
+        let mut x: i32 = 0;
+        for (id, model) in models.iter().enumerate() {
+            let half = model.size.map(|s| s / 2);
+            let translation = [x.checked_add(half[0]).ok_or_else(|| error(OUT_OF_RANGE))?, half[1], half[2]];
+            instances.push((id as i32, Transform { translation, ..Transform::IDENTITY }));
+            x = x.checked_add(model.size[0]).and_then(|x| x.checked_add(1)).ok_or_else(|| error(OUT_OF_RANGE))?;
+        }
+    }
+
+    let mut worlds = Vec::new();
+    for (model_id, transform) in instances {
+        let model = models.get(model_id as usize).ok_or_else(|| error("a shape names a missing model"))?;
+        if model.voxels.is_empty() {
+            continue;
+        }
+        // Models rotate and translate around their center
+        let half = model.size.map(|s| s / 2);
+        let cells: Vec<([i32; 3], u8)> = model
+            .voxels
+            .iter()
+            .map(|&(v, index)| {
+                let local = [v[0] as i32 - half[0], v[1] as i32 - half[1], v[2] as i32 - half[2]];
+                let [x, y, z] = transform.apply(local)?;
+                Some(([x, z, y.checked_neg()?], index)) // Z up to Y up
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| error(OUT_OF_RANGE))?;
+
+        let min: [i32; 3] = std::array::from_fn(|axis| cells.iter().map(|(c, _)| c[axis]).min().unwrap_or(0));
+        let max: [i32; 3] = std::array::from_fn(|axis| cells.iter().map(|(c, _)| c[axis]).max().unwrap_or(0));
+        let size = std::array::from_fn::<usize, 3, _>(|axis| (max[axis] - min[axis] + 1) as usize);
+        let origin = position + Vec3::new(min[0] as f32, min[1] as f32, min[2] as f32) * voxel_size;
+
+        let mut world = VoxelWorld::new(size[0], size[1], size[2], origin, voxel_size);
+        let mut block_ids: HashMap<u8, BlockId> = HashMap::new();
+        for (cell, index) in cells {
+            let id = match block_ids.get(&index) {
+                Some(&id) => id,
+                None => {
+                    let color = palette_color(palette.as_deref(), index);
+                    let id = world
+                        .add_block(palette_material(color, materials.get(&index)))
+                        .map_err(|e| ImportError::new(path, e.to_string()))?;
+                    block_ids.insert(index, id);
+                    id
+                }
+            };
+            let [x, y, z] = std::array::from_fn(|axis| (cell[axis] - min[axis]) as usize);
+            world.set(x, y, z, id);
+        }
+        worlds.push(world);
+    }
+    Ok(worlds)
+}
+
+/// Walks the scene graph from `id`, collecting every model with its accumulated transform.
+fn collect_instances(
+    nodes: &HashMap<i32, Node>,
+    id: i32,
+    parent: &Transform,
+    instances: &mut Vec<(i32, Transform)>,
+    depth: usize,
+) -> Result<(), &'static str> {
+    if depth > 64 {
+        return Err(BROKEN_GRAPH); // A cycle
+    }
+    match nodes.get(&id).ok_or(BROKEN_GRAPH)? {
+        Node::Transform { hidden: true, .. } => {}
+        Node::Transform { child, transform, .. } => {
+            let transform = parent.then(transform).ok_or(OUT_OF_RANGE)?;
+            collect_instances(nodes, *child, &transform, instances, depth + 1)?
+        }
+        Node::Group { children } => {
+            for &child in children {
+                collect_instances(nodes, child, parent, instances, depth + 1)?;
+            }
+        }
+        Node::Shape { models } => instances.extend(models.iter().map(|&model| (model, *parent))),
+    }
+    Ok(())
+}
+
+/// Color of palette entry `index` (1 to 255), from the default palette without an `RGBA` chunk.
+fn palette_color(palette: Option<&[[u8; 4]]>, index: u8) -> Color {
+    match palette.and_then(|palette| palette.get(index as usize - 1)) {
+        Some(&[r, g, b, _]) => Color::new(r as i32, g as i32, b as i32),
+        None => default_palette_color(index),
+    }
+}
+
+/// MagicaVoxel's built-in palette: the 6×6×6 web color cube from white to (almost) black,
+/// red outermost, then ramps of the missing shades of red, green, blue and grey.
+fn default_palette_color(index: u8) -> Color {
+    const CUBE: [i32; 6] = [255, 204, 153, 102, 51, 0];
+    const RAMP: [i32; 10] = [238, 221, 187, 170, 136, 119, 85, 68, 34, 17];
+    match index as usize {
+        0 => Color::new(0, 0, 0),
+        i @ 1..=215 => {
+            let c = i - 1;
+            Color::new(CUBE[c / 36], CUBE[c / 6 % 6], CUBE[c % 6])
+        }
+        i => {
+            let (ramp, v) = ((i - 216) / 10, RAMP[(i - 216) % 10]);
+            match ramp {
+                0 => Color::new(v, 0, 0),
+                1 => Color::new(0, v, 0),
+                2 => Color::new(0, 0, v),
+                _ => Color::new(v, v, v),
+            }
+        }
+    }
+}
+
+fn palette_material(color: Color, properties: Option<&Dict>) -> Material {
+    let number = |key: &str, default: f32| {
+        properties.and_then(|p| p.get(key)).and_then(|v| v.parse::<f32>().ok()).unwrap_or(default)
+    };
+    let kind = properties.and_then(|p| p.get("_type")).map_or("_diffuse", String::as_str);
+    let roughness = number("_rough", 0.5).clamp(0.0, 1.0);
+    let spec = (2.0 / roughness.max(0.05).powi(4) - 2.0).clamp(1.0, 1000.0);
+
+    let mut material = Material::new(color, spec, [0.9, 0.5 * (1.0 - roughness)], 0.0, 0.0, 1.0);
+    match kind {
+        "_metal" => {
+            material.reflectivity = number("_metal", 1.0).clamp(0.0, 1.0) * (1.0 - roughness);
+            material.albedo[0] = 1.0 - material.reflectivity;
+            material.fresnel = FresnelMode::Schlick;
+        }
+        "_glass" => {
+            material.transparency = number("_trans", number("_alpha", 0.5)).clamp(0.0, 1.0);
+            material.reflectivity = 0.05;
+            material.refraction_index = 1.0 + number("_ior", 0.3); // Stored as the index minus one
+            material.fresnel = FresnelMode::Exact;
+        }
+        "_emit" => {
+            let strength = number("_emit", 0.0) * (1.0 + number("_flux", 0.0));
+            material.emission = HdrColor::from(color) * strength;
+        }
+        _ => {}
+    }
+    material
+}
+
+/// Little-endian cursor over chunk data.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(count)?)?;
+        self.offset += count;
+        Some(bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.bytes(4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32_array(&mut self) -> Option<[i32; 3]> {
+        Some([self.i32()?, self.i32()?, self.i32()?])
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = usize::try_from(self.i32()?).ok()?;
+        self.bytes(length).map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+
+    fn dict(&mut self) -> Option<Dict> {
+        let count = self.i32()?;
+        (0..count).map(|_| Some((self.string()?, self.string()?))).collect()
+    }
+
+    /// Chunk id and content; children are read as following chunks.
+    fn chunk(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        let id = self.bytes(4)?;
+        let content_size = usize::try_from(self.i32()?).ok()?;
+        self.i32()?; // Children size
+        Some((id, self.bytes(content_size)?))
+    }
+
+    fn voxels(&mut self) -> Option<Vec<([u8; 3], u8)>> {
+        let count = usize::try_from(self.i32()?).ok()?;
+        let bytes = self.bytes(count.checked_mul(4)?)?;
+        Some(bytes.chunks_exact(4).map(|v| ([v[0], v[1], v[2]], v[3])).filter(|&(_, index)| index > 0).collect())
+    }
+
+    fn transform_node(&mut self) -> Option<(i32, Node)> {
+        let id = self.i32()?;
+        let attributes = self.dict()?;
+        let child = self.i32()?;
+        self.i32()?; // Reserved
+        self.i32()?; // Layer
+        let frames = self.i32()?;
+        let frame = if frames > 0 { self.dict()? } else { Dict::new() };
+
+        let mut transform = Transform::IDENTITY;
+        if let Some(t) = frame.get("_t") {
+            let values: Vec<i32> = t.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            if let [x, y, z] = values[..] {
+                transform.translation = [x, y, z];
+            }
+        }
+        if let Some(r) = frame.get("_r").and_then(|r| r.parse::<u8>().ok()) {
+            transform.rotation = rotation_matrix(r);
+        }
+        let hidden = attributes.get("_hidden").is_some_and(|h| h == "1");
+        Some((id, Node::Transform { child, transform, hidden }))
+    }
+
+    fn group_node(&mut self) -> Option<(i32, Node)> {
+        let id = self.i32()?;
+        self.dict()?;
+        let count = self.i32()?;
+        let children = (0..count).map(|_| self.i32()).collect::<Option<_>>()?;
+        Some((id, Node::Group { children }))
+    }
+
+    fn shape_node(&mut self) -> Option<(i32, Node)> {
+        let id = self.i32()?;
+        self.dict()?;
+        let count = self.i32()?;
+        let models = (0..count)
+            .map(|_| {
+                let model = self.i32()?;
+                self.dict()?;
+                Some(model)
+            })
+            .collect::<Option<_>>()?;
+        Some((id, Node::Shape { models }))
+    }
+}
+
+/// Decodes the packed rotation byte: the column of the 1 in the first two rows, then a sign
+/// bit per row.
+fn rotation_matrix(packed: u8) -> [[i32; 3]; 3] {
+    let first = (packed & 3) as usize;
+    let second = ((packed >> 2) & 3) as usize;
+    let third = 3usize.saturating_sub(first + second).min(2);
+    let mut rotation = [[0; 3]; 3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        let negative = packed & (1 << (4 + row)) != 0;
+        rotation[row][column.min(2)] = if negative { -1 } else { 1 };
+    }
+    rotation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bounded;
+    use crate::ray_intersect::RayIntersect;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as i32).to_le_bytes());
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A 2×2×2 model with voxels of palette entries 1 (white) and 36 (red).
+    fn model() -> Vec<u8> {
+        let mut bytes = chunk(b"SIZE", &ints(&[2, 2, 2]));
+        bytes.extend(chunk(b"XYZI", &[ints(&[2]), vec![0, 0, 0, 1, 1, 1, 1, 36]].concat()));
+        bytes
+    }
+
+    fn file(chunks: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150i32.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend((chunks.len() as i32).to_le_bytes());
+        bytes.extend(chunks);
+        bytes
+    }
+
+    fn parse(data: &[u8]) -> Result<Vec<VoxelWorld>, ImportError> {
+        parse_vox(data, Path::new("test.vox"), Vec3::zeros(), 1.0)
+    }
+
+    #[test]
+    fn minimal_file() {
+        let worlds = parse(&file(&model())).unwrap();
+        assert_eq!(worlds.len(), 1);
+        let bounds = worlds[0].bounds();
+        assert_eq!(bounds.max - bounds.min, Vec3::new(2.0, 2.0, 2.0));
+
+        // Default palette: entry 1 is white, entry 36 pure red. MagicaVoxel's (0, 0, 0) lands on
+        // the origin and its (1, 1, 1) one voxel up, along +X and along -Z
+        assert_eq!(bounds.min, Vec3::new(0.0, 0.0, -1.0));
+        let white = worlds[0].ray_intersect(&Vec3::new(0.5, -5.0, 0.5), &Vec3::new(0.0, 1.0, 0.0));
+        let red = worlds[0].ray_intersect(&Vec3::new(1.5, 5.0, -0.5), &Vec3::new(0.0, -1.0, 0.0));
+        assert!(white.is_intersecting && red.is_intersecting);
+        assert_eq!((white.material.diffuse.r, white.material.diffuse.g, white.material.diffuse.b), (255, 255, 255));
+        assert_eq!((red.material.diffuse.r, red.material.diffuse.g, red.material.diffuse.b), (255, 0, 0));
+    }
+
+    #[test]
+    fn models_without_scene_graph_are_laid_out() {
+        let worlds = parse(&file(&[model(), model()].concat())).unwrap();
+        assert_eq!(worlds.len(), 2);
+        assert_eq!(worlds[1].bounds().min.x - worlds[0].bounds().min.x, 3.0);
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let data = file(&model());
+        let error = parse(&data[..data.len() - 3]).unwrap_err();
+        assert_eq!(error.message, "truncated chunk");
+        assert!(parse(&data[..6]).is_err());
+    }
+
+    fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = ints(&[pairs.len() as i32]);
+        for text in pairs.iter().flat_map(|&(key, value)| [key, value]) {
+            bytes.extend(ints(&[text.len() as i32]));
+            bytes.extend(text.as_bytes());
+        }
+        bytes
+    }
+
+    /// `nTRN` node `id` moving `child` by `translation`.
+    fn translate(id: i32, child: i32, translation: &str) -> Vec<u8> {
+        let content = [ints(&[id]), dict(&[]), ints(&[child, -1, 0, 1]), dict(&[("_t", translation)])].concat();
+        chunk(b"nTRN", &content)
+    }
+
+    #[test]
+    fn extreme_translations() {
+        let shape = chunk(b"nSHP", &ints(&[1, 0, 1, 0, 0]));
+        let parse_graph = |nodes: &[Vec<u8>]| parse(&file(&[model(), nodes.concat(), shape.clone()].concat()));
+
+        let worlds = parse_graph(&[translate(0, 1, "100 -7 3")]).unwrap();
+        assert_eq!(worlds[0].bounds().min, Vec3::new(99.0, 2.0, 7.0));
+
+        // Negating MagicaVoxel's Y, and two translations that only overflow once combined
+        let error = parse_graph(&[translate(0, 1, "0 -2147483648 0")]).unwrap_err();
+        assert_eq!(error.message, "voxel positions out of range");
+        let error = parse_graph(&[translate(0, 2, "2147483647 0 0"), translate(2, 1, "2147483647 0 0")]).unwrap_err();
+        assert_eq!(error.message, "voxel positions out of range");
+    }
+
+    #[test]
+    fn missing_model_reference() {
+        let mut chunks = model();
+        // nTRN 0 -> nSHP 1 -> model 5, which the file doesn't have
+        chunks.extend(chunk(b"nTRN", &ints(&[0, 0, 1, -1, 0, 1, 0])));
+        chunks.extend(chunk(b"nSHP", &ints(&[1, 0, 1, 5, 0])));
+        let error = parse(&file(&chunks)).unwrap_err();
+        assert_eq!(error.message, "a shape names a missing model");
+    }
+}
@@ -0,0 +1,370 @@
+use nalgebra_glm::Vec3;
+use std::fmt;
+use crate::bvh::{Aabb, Bounded};
+use crate::material::Material;
+use crate::object::{box_intersect, Face};
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+/// Identifier of a block type inside a `VoxelWorld`.
+pub type BlockId = u16;
+
+/// Block id of empty space.
+pub const AIR: BlockId = 0;
+
+/// Error returned when a `VoxelWorld` runs out of block ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyBlockTypes;
+
+impl fmt::Display for TooManyBlockTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a voxel world holds at most {} block types", BlockId::MAX)
+    }
+}
+
+impl std::error::Error for TooManyBlockTypes {}
+
+/// Dense grid of axis-aligned blocks, traversed with an Amanatides–Woo DDA.
+#[derive(Debug, Clone)]
+pub struct VoxelWorld {
+    pub origin: Vec3,     // Minimum corner of the voxel at (0, 0, 0)
+    pub voxel_size: f32,  // Edge length of every voxel
+    size: [usize; 3],     // Number of voxels along x, y and z
+    blocks: Vec<BlockId>,
+    block_types: Vec<[Material; 6]>, // Face materials of block id `i` are stored at `i - 1`
+}
+
+impl VoxelWorld {
+    /// Creates an empty world of `size_x * size_y * size_z` voxels.
+    pub fn new(size_x: usize, size_y: usize, size_z: usize, origin: Vec3, voxel_size: f32) -> Self {
+        VoxelWorld {
+            origin,
+            voxel_size,
+            size: [size_x, size_y, size_z],
+            blocks: vec![AIR; size_x * size_y * size_z],
+            block_types: Vec::new(),
+        }
+    }
+
+    /// Registers a block type with one material on every face and returns the id to place it with.
+    pub fn add_block(&mut self, material: Material) -> Result<BlockId, TooManyBlockTypes> {
+        self.add_block_faces(std::array::from_fn(|_| material.clone()))
+    }
+
+    /// Registers a block type with one material per face, indexed by `Face`.
+    pub fn add_block_faces(&mut self, faces: [Material; 6]) -> Result<BlockId, TooManyBlockTypes> {
+        let id = BlockId::try_from(self.block_types.len() + 1).map_err(|_| TooManyBlockTypes)?;
+        self.block_types.push(faces);
+        Ok(id)
+    }
+
+    /// Returns the block at the given voxel, or `AIR` outside the grid.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return AIR;
+        }
+        self.blocks[self.index(x, y, z)]
+    }
+
+    /// Places a block; positions outside the grid are ignored.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return;
+        }
+        let index = self.index(x, y, z);
+        self.blocks[index] = block;
+    }
+
+    /// Distance a ray starting inside a solid voxel travels through touching voxels of the
+    /// same block type before reaching a different one; None if it starts in air.
+    pub fn exit_distance(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Option<f32> {
+        if !self.bounds().contains(ray_origin) {
+            return None;
+        }
+        let mut traversal = self.traversal(ray_origin, ray_direction, 0.0);
+        let [x, y, z] = traversal.cell;
+        let block = self.get(x, y, z);
+        if block == AIR {
+            return None;
+        }
+
+        loop {
+            let [x, y, z] = traversal.cell;
+            if self.get(x, y, z) != block {
+                return Some(traversal.t_entered);
+            }
+            if traversal.step(self.size).is_none() {
+                return Some(traversal.t_entered); // Left the grid
+            }
+        }
+    }
+
+    /// Prepares an Amanatides–Woo walk from the point `t_enter` along the ray, inside the grid.
+    fn traversal(&self, ray_origin: &Vec3, ray_direction: &Vec3, t_enter: f32) -> Traversal {
+        let inv_direction = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        let entry = ray_origin + ray_direction * t_enter;
+        let mut traversal = Traversal {
+            cell: [0; 3],
+            step: [0; 3],
+            t_max: [f32::INFINITY; 3],
+            t_delta: [f32::INFINITY; 3],
+            t_entered: t_enter,
+        };
+
+        for axis in 0..3 {
+            let local = (entry[axis] - self.origin[axis]) / self.voxel_size;
+            traversal.cell[axis] = (local.floor().max(0.0) as usize).min(self.size[axis].saturating_sub(1));
+
+            if ray_direction[axis] > 0.0 {
+                traversal.step[axis] = 1;
+                let boundary = self.origin[axis] + (traversal.cell[axis] + 1) as f32 * self.voxel_size;
+                traversal.t_max[axis] = (boundary - ray_origin[axis]) * inv_direction[axis];
+                traversal.t_delta[axis] = self.voxel_size * inv_direction[axis];
+            } else if ray_direction[axis] < 0.0 {
+                traversal.step[axis] = -1;
+                let boundary = self.origin[axis] + traversal.cell[axis] as f32 * self.voxel_size;
+                traversal.t_max[axis] = (boundary - ray_origin[axis]) * inv_direction[axis];
+                traversal.t_delta[axis] = -self.voxel_size * inv_direction[axis];
+            }
+        }
+        traversal
+    }
+
+    /// Calls `visit` with the box and face of every glowing voxel face a ray can reach, that
+    /// is every one not covered by a neighbour rays cannot pass through.
+    pub(crate) fn for_each_emissive_face(&self, mut visit: impl FnMut(Vec3, Vec3, Face)) {
+        let opaque = |block: BlockId| {
+            block != AIR && self.block_types[block as usize - 1].iter().all(|m| m.transparency <= 0.0 && m.cutout.is_none())
+        };
+        for y in 0..self.size[1] {
+            for z in 0..self.size[2] {
+                for x in 0..self.size[0] {
+                    let block = self.get(x, y, z);
+                    if block == AIR {
+                        continue;
+                    }
+                    let min = self.origin + Vec3::new(x as f32, y as f32, z as f32) * self.voxel_size;
+                    let max = min + Vec3::new(self.voxel_size, self.voxel_size, self.voxel_size);
+                    for face in Face::ALL {
+                        if !self.block_types[block as usize - 1][face as usize].is_emissive() {
+                            continue;
+                        }
+                        let normal = face.normal();
+                        let neighbour = [0, 1, 2].map(|axis| [x, y, z][axis].checked_add_signed(normal[axis] as isize));
+                        let covered = match neighbour {
+                            [Some(nx), Some(ny), Some(nz)] => opaque(self.get(nx, ny, nz)),
+                            _ => false, // Outside the grid
+                        };
+                        if !covered {
+                            visit(min, max, face);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (y * self.size[2] + z) * self.size[0] + x
+    }
+
+    /// Intersects the box of a voxel, so hits shade exactly like a placed `Cube`.
+    fn block_hit(&self, cell: [usize; 3], block: BlockId, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let min = self.origin + Vec3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) * self.voxel_size;
+        let max = min + Vec3::new(self.voxel_size, self.voxel_size, self.voxel_size);
+        box_intersect(&min, &max, &self.block_types[block as usize - 1], ray_origin, ray_direction)
+    }
+}
+
+/// State of a DDA walk through the grid.
+struct Traversal {
+    cell: [usize; 3],
+    step: [isize; 3],
+    t_max: [f32; 3],   // Ray distance at which the next boundary of each axis is crossed
+    t_delta: [f32; 3], // Ray distance between two boundaries of each axis
+    t_entered: f32,    // Ray distance at which the current cell was entered
+}
+
+impl Traversal {
+    /// Moves into the next cell along the ray; None once the ray leaves a grid of `size`.
+    fn step(&mut self, size: [usize; 3]) -> Option<()> {
+        let t_max = self.t_max;
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+
+        self.t_entered = t_max[axis];
+        match self.cell[axis].checked_add_signed(self.step[axis]) {
+            Some(next) if next < size[axis] => self.cell[axis] = next,
+            _ => return None,
+        }
+        self.t_max[axis] += self.t_delta[axis];
+        Some(())
+    }
+}
+
+impl Bounded for VoxelWorld {
+    fn bounds(&self) -> Aabb {
+        let extent = Vec3::new(self.size[0] as f32, self.size[1] as f32, self.size[2] as f32) * self.voxel_size;
+        Aabb::new(self.origin, self.origin + extent)
+    }
+}
+
+impl RayIntersect for VoxelWorld {
+    /// Walks the voxels pierced by the ray in order and returns the first solid one hit.
+    fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
+        let inv_direction = Vec3::new(1.0 / ray_direction.x, 1.0 / ray_direction.y, 1.0 / ray_direction.z);
+        let t_enter = match self.bounds().hit(ray_origin, &inv_direction, f32::INFINITY) {
+            Some(t) => t,
+            None => return Intersect::empty(),
+        };
+
+        let mut traversal = self.traversal(ray_origin, ray_direction, t_enter);
+
+        loop {
+            let cell = traversal.cell;
+            let block = self.get(cell[0], cell[1], cell[2]);
+            if block != AIR {
+                // The voxel containing the ray origin reports no hit, matching `Cube`
+                let intersect = self.block_hit(cell, block, ray_origin, ray_direction);
+                if intersect.is_intersecting {
+                    return intersect;
+                }
+            }
+
+            if traversal.step(self.size).is_none() {
+                return Intersect::empty(); // Left the grid
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::object::Cube;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn material() -> Material {
+        Material::new(Color::new(255, 255, 255), 10.0, [1.0, 0.0], 0.0, 0.0, 1.0)
+    }
+
+    /// 4×4×4 unit grid at the origin with the given cells filled.
+    fn world(cells: &[[usize; 3]]) -> VoxelWorld {
+        let mut world = VoxelWorld::new(4, 4, 4, Vec3::zeros(), 1.0);
+        let block = world.add_block(material()).unwrap();
+        for &[x, y, z] in cells {
+            world.set(x, y, z, block);
+        }
+        world
+    }
+
+    fn assert_hit(world: &VoxelWorld, origin: Vec3, direction: Vec3, distance: f32, normal: Vec3) {
+        let intersect = world.ray_intersect(&origin, &direction);
+        assert!(intersect.is_intersecting, "ray from {:?} along {:?} missed", origin, direction);
+        assert!((intersect.distance - distance).abs() < 1e-4, "distance {} != {}", intersect.distance, distance);
+        assert_eq!(intersect.normal, normal);
+    }
+
+    #[test]
+    fn enters_from_outside() {
+        let world = world(&[[2, 1, 1]]);
+        assert_hit(&world, Vec3::new(-5.0, 1.5, 1.5), Vec3::new(1.0, 0.0, 0.0), 7.0, Vec3::new(-1.0, 0.0, 0.0));
+        assert_hit(&world, Vec3::new(2.5, 9.0, 1.5), Vec3::new(0.0, -1.0, 0.0), 7.0, Vec3::new(0.0, 1.0, 0.0));
+
+        let direction = Vec3::new(1.0, 0.2, 0.1).normalize();
+        let origin = Vec3::new(2.2, 1.3, 1.6) - direction * 6.0;
+        let intersect = world.ray_intersect(&origin, &direction);
+        assert!(intersect.is_intersecting);
+        assert_eq!(intersect.normal, Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn axis_parallel_rays() {
+        let world = world(&[[0, 0, 0], [3, 3, 3], [1, 2, 3]]);
+        for (origin, direction, distance, normal) in [
+            (Vec3::new(0.5, 0.5, 10.0), Vec3::new(0.0, 0.0, -1.0), 9.0, Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(3.5, 3.5, -10.0), Vec3::new(0.0, 0.0, 1.0), 13.0, Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(10.0, 2.5, 3.5), Vec3::new(-1.0, 0.0, 0.0), 8.0, Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(1.5, -10.0, 3.5), Vec3::new(0.0, 1.0, 0.0), 12.0, Vec3::new(0.0, -1.0, 0.0)),
+        ] {
+            assert_hit(&world, origin, direction, distance, normal);
+        }
+        // Rows with nothing in them
+        assert!(!world.ray_intersect(&Vec3::new(2.5, 0.5, -10.0), &Vec3::new(0.0, 0.0, 1.0)).is_intersecting);
+        assert!(!world.ray_intersect(&Vec3::new(-10.0, 1.5, 1.5), &Vec3::new(1.0, 0.0, 0.0)).is_intersecting);
+    }
+
+    #[test]
+    fn ray_starting_inside_a_solid_cell() {
+        // The cell holding the origin is skipped, like a `Cube` seen from inside
+        let pair = world(&[[1, 1, 1], [2, 1, 1]]);
+        assert_hit(&pair, Vec3::new(1.5, 1.5, 1.5), Vec3::new(1.0, 0.0, 0.0), 0.5, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(!pair.ray_intersect(&Vec3::new(1.5, 1.5, 1.5), &Vec3::new(-1.0, 0.0, 0.0)).is_intersecting);
+
+        let gap = world(&[[1, 1, 1], [1, 1, 3]]);
+        assert_hit(&gap, Vec3::new(1.5, 1.5, 1.5), Vec3::new(0.0, 0.0, 1.0), 1.5, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn grid_boundary() {
+        let world = world(&[[3, 3, 3], [0, 0, 0]]);
+        // Leaving the grid from inside without meeting a block
+        assert!(!world.ray_intersect(&Vec3::new(2.5, 2.5, 2.5), &Vec3::new(0.0, 1.0, 0.0)).is_intersecting);
+        // Passing just outside the grid
+        assert!(!world.ray_intersect(&Vec3::new(4.01, 3.5, -10.0), &Vec3::new(0.0, 0.0, 1.0)).is_intersecting);
+        // Entering through the far corner cell and through the grid's minimum corner
+        assert_hit(&world, Vec3::new(3.5, 3.5, 10.0), Vec3::new(0.0, 0.0, -1.0), 6.0, Vec3::new(0.0, 0.0, 1.0));
+        let direction = Vec3::new(1.0, 1.0, 1.0).normalize();
+        let intersect = world.ray_intersect(&(Vec3::new(0.5, 0.5, 0.5) - direction * 5.0), &direction);
+        assert!(intersect.is_intersecting);
+        assert!((intersect.distance - (5.0 - 0.5 * 3f32.sqrt())).abs() < 1e-4);
+        // Out-of-range writes are ignored and reads are air
+        let mut world = world;
+        world.set(4, 0, 0, 1);
+        assert_eq!(world.get(4, 0, 0), AIR);
+    }
+
+    #[test]
+    fn block_ids_run_out() {
+        let mut world = VoxelWorld::new(1, 1, 1, Vec3::zeros(), 1.0);
+        for expected in 1..=BlockId::MAX {
+            assert_eq!(world.add_block(material()), Ok(expected));
+        }
+        assert_eq!(world.add_block(material()), Err(TooManyBlockTypes));
+    }
+
+    #[test]
+    fn matches_cubes() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let cells: Vec<[usize; 3]> = (0..20).map(|_| [rng.gen_range(0..4), rng.gen_range(0..4), rng.gen_range(0..4)]).collect();
+        let world = world(&cells);
+        let cubes: Vec<Cube> = cells
+            .iter()
+            .map(|&[x, y, z]| {
+                let min = Vec3::new(x as f32, y as f32, z as f32);
+                Cube::new(min, min + Vec3::new(1.0, 1.0, 1.0), material())
+            })
+            .collect();
+
+        for _ in 0..2000 {
+            let origin = Vec3::new(rng.gen_range(-2.0..6.0), rng.gen_range(-2.0..6.0), rng.gen_range(-2.0..6.0));
+            let direction = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)).normalize();
+            let expected = cubes
+                .iter()
+                .map(|cube| cube.ray_intersect(&origin, &direction))
+                .filter(|i| i.is_intersecting)
+                .map(|i| i.distance)
+                .reduce(f32::min);
+            let actual = world.ray_intersect(&origin, &direction);
+            assert_eq!(expected.is_some(), actual.is_intersecting);
+            if let Some(distance) = expected {
+                assert!((distance - actual.distance).abs() < 1e-4);
+            }
+        }
+    }
+}